@@ -1,48 +1,126 @@
 mod route;
 
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
 use anyhow::{anyhow, Result};
 use axum::{routing, Router};
-use camino::Utf8PathBuf;
-use notify::{RecursiveMode, Watcher};
-
-async fn run() -> Result<()> {
-    let mut args = std::env::args();
-    let port = args
-        .nth(1)
-        .ok_or_else(|| anyhow!("Expected a port number as a first argument"))?;
-    if let Some(wd) = args.next() {
-        std::env::set_current_dir(wd)?;
-    }
+use camino::{Utf8Path, Utf8PathBuf};
+use clap::Parser;
+use custard_lib::{
+    config::Config,
+    frontmatter_file::{
+        keeper::{ArcRwLock, Options},
+        Keeper,
+    },
+};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use tracing::info;
+use tracing_subscriber::filter::LevelFilter;
 
-    let current_dir = std::env::current_dir()?;
-    let current_dir = Utf8PathBuf::try_from(current_dir)?;
+/// Serves the markdown files in a directory over HTTP
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    /// Port to listen on
+    #[arg(short, long)]
+    port: Option<u16>,
+    /// IP address to listen on [default: 0.0.0.0]
+    #[arg(short, long)]
+    bind: Option<IpAddr>,
+    /// Directory to serve [default: the current directory]
+    #[arg(short, long)]
+    dir: Option<Utf8PathBuf>,
+    /// error, warn, info, debug, trace or off [default: info]
+    #[arg(short, long)]
+    log_level: Option<LevelFilter>,
+    /// Config file [default: custard.toml in the served directory, if there is one]
+    #[arg(short, long)]
+    config: Option<Utf8PathBuf>,
+}
 
-    let keeper = custard_lib::frontmatter_file::Keeper::new(&current_dir)?;
+fn watch(dir: &Utf8Path, options: Options) -> Result<(ArcRwLock, RecommendedWatcher)> {
+    let keeper = Keeper::with_options(dir, options)?;
 
-    let markdown_files = custard_lib::frontmatter_file::keeper::ArcMutex::new(keeper);
+    let markdown_files = ArcRwLock::new(keeper);
 
     let mut watcher = notify::recommended_watcher(markdown_files.clone())?;
 
-    watcher.watch(current_dir.as_std_path(), RecursiveMode::NonRecursive)?;
+    watcher.watch(dir.as_std_path(), RecursiveMode::Recursive)?;
 
-    let app = Router::new()
+    Ok((markdown_files, watcher))
+}
+
+fn routes(markdown_files: ArcRwLock) -> Router {
+    Router::new()
         .route(
             "/frontmatter/list",
             routing::post(route::frontmatter_list::post).get(route::frontmatter_list::get),
         )
         .route(
-            "/frontmatter/file/:name",
+            "/frontmatter/file/*name",
             routing::post(route::frontmatter_file::post).get(route::frontmatter_file::get),
         )
         .route(
             "/frontmatter/collate_strings/:key",
             routing::post(route::collate_strings::post).get(route::collate_strings::get),
         )
-        .with_state(markdown_files);
+        .route(
+            "/search",
+            routing::post(route::search::post).get(route::search::get),
+        )
+        .route("/links/broken", routing::get(route::links::broken))
+        .route("/health/errors", routing::get(route::health::errors))
+        .with_state(markdown_files)
+}
+
+async fn run() -> Result<()> {
+    let cli = Cli::parse();
+
+    let current_dir = Utf8PathBuf::try_from(std::env::current_dir()?)?;
+    let config = Config::find(
+        cli.config.as_deref(),
+        cli.dir.as_deref().unwrap_or(&current_dir),
+    )?;
+
+    let log_level = config.log_level(cli.log_level)?;
+    tracing_subscriber::fmt().with_max_level(log_level).init();
+
+    let port = cli
+        .port
+        .or(config.http.port)
+        .ok_or_else(|| anyhow!("Expected a port, either with --port or in the config file"))?;
+    let bind = cli
+        .bind
+        .or(config.http.bind)
+        .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+    let dir = cli.dir.as_ref().or(config.dir.as_ref());
+    if let Some(dir) = dir {
+        std::env::set_current_dir(dir)?;
+    }
+
+    let current_dir = std::env::current_dir()?;
+    let current_dir = Utf8PathBuf::try_from(current_dir)?;
+
+    let mut app = Router::new();
+    // Keeps watching for as long as the server runs
+    let mut watchers = Vec::new();
+
+    // With only collections configured, the current directory isn't served as well
+    if dir.is_some() || config.collections.is_empty() {
+        let (markdown_files, watcher) = watch(&current_dir, config.keeper_options()?)?;
+        app = app.merge(routes(markdown_files));
+        watchers.push(watcher);
+    }
+    for (name, collection) in &config.collections {
+        let options = config.collection_options(collection)?;
+        let (markdown_files, watcher) = watch(&collection.dir, options)?;
+        app = app.nest(&format!("/c/{name}"), routes(markdown_files));
+        watchers.push(watcher);
+    }
 
-    let socket_addr_string = format!("0.0.0.0:{port}");
-    println!("Binding to {socket_addr_string}");
-    axum::Server::bind(&socket_addr_string.parse()?)
+    let socket_addr = SocketAddr::new(bind, port);
+    info!("Binding to {socket_addr}");
+    axum::Server::bind(&socket_addr)
         .serve(app.into_make_service())
         .await?;
 
@@ -1,5 +1,4 @@
 use std::collections::HashMap;
-use std::str::FromStr;
 
 use axum::{
     extract::{Path, Query, State},
@@ -7,15 +6,15 @@ use axum::{
     Json,
 };
 
-use super::lock_keeper;
+use super::apply_intersect_param;
 
-use custard_lib::{frontmatter_file, frontmatter_query::FrontmatterQueryMap};
+use custard_lib::{frontmatter_file, frontmatter_query::FrontmatterQuery};
 
 pub async fn get(
-    State(markdown_files): State<frontmatter_file::keeper::ArcMutex>,
+    State(markdown_files): State<frontmatter_file::keeper::ArcRwLock>,
     Path(key): Path<String>,
-) -> Result<Json<Vec<String>>, StatusCode> {
-    let keeper = &*lock_keeper(&markdown_files)?;
+) -> Json<Vec<String>> {
+    let keeper = &*markdown_files.read();
 
     let values = custard_lib::collate::collate(
         keeper,
@@ -25,32 +24,24 @@ pub async fn get(
         },
     );
 
-    Ok(Json(values))
+    Json(values)
 }
 
 pub async fn post(
-    State(markdown_files): State<frontmatter_file::keeper::ArcMutex>,
+    State(markdown_files): State<frontmatter_file::keeper::ArcRwLock>,
     params: Query<HashMap<String, String>>,
     Path(key): Path<String>,
-    Json(query_map): Json<FrontmatterQueryMap>,
+    Json(query): Json<FrontmatterQuery>,
 ) -> Result<Json<Vec<String>>, StatusCode> {
-    let keeper = &*lock_keeper(&markdown_files)?;
+    let keeper = &*markdown_files.read();
 
-    let intersect = params
-        .get("intersect")
-        .map(|p| bool::from_str(p))
-        .transpose()
-        .map_err(|_| StatusCode::BAD_REQUEST)?
-        .unwrap_or_default();
+    let query = apply_intersect_param(&params, query)?;
 
     let values = custard_lib::collate::collate(
         keeper,
         custard_lib::collate::Args {
             key: key.as_str(),
-            query: Some(custard_lib::frontmatter_query::FrontmatterQuery {
-                map: query_map,
-                intersect,
-            }),
+            query: Some(query),
         },
     );
 
@@ -3,15 +3,17 @@ use std::{collections::HashMap, ops::Deref};
 use anyhow::Result;
 use axum::{
     extract::{Path, Query, State},
-    http::{HeaderMap, StatusCode},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
     Json,
 };
 use custard_lib::{
     frontmatter_file::{self, FrontmatterFile},
-    frontmatter_query::{FrontmatterQuery, FrontmatterQueryMap},
+    frontmatter_query::FrontmatterQuery,
 };
+use tracing::error;
 
-use super::lock_keeper;
+use super::{apply_intersect_param, nulls_first_param, sort_param};
 
 fn assign_headers(
     file: &FrontmatterFile,
@@ -21,34 +23,34 @@ fn assign_headers(
     let mut headers = HeaderMap::new();
     let frontmatter = file.frontmatter();
     let frontmatter_string = serde_json::to_string(&frontmatter).map_err(|err| {
-        eprintln!(
+        error!(
             "Failed to serialize frontmatter ({frontmatter:?}) as JSON during get request: {err}"
         );
         StatusCode::INTERNAL_SERVER_ERROR
     })?;
     let frontmatter_header_value = frontmatter_string.parse().map_err(|err| {
-        eprintln!("Failed to parse header value ({frontmatter_string:?}): {err}");
+        error!("Failed to parse header value ({frontmatter_string:?}): {err}");
         StatusCode::INTERNAL_SERVER_ERROR
     })?;
     headers.insert("x-frontmatter", frontmatter_header_value);
 
     let created_string = file.created().to_rfc3339();
     let created_header_value = created_string.parse().map_err(|err| {
-        eprintln!("Failed to parse 'created' header value ({created_string:?}): {err}");
+        error!("Failed to parse 'created' header value ({created_string:?}): {err}");
         StatusCode::INTERNAL_SERVER_ERROR
     })?;
     headers.insert("x-created", created_header_value);
 
     let modified_string = file.modified().to_rfc3339();
     let modified_header_value = modified_string.parse().map_err(|err| {
-        eprintln!("Failed to parse 'modified' header value ({modified_string:?}): {err}");
+        error!("Failed to parse 'modified' header value ({modified_string:?}): {err}");
         StatusCode::INTERNAL_SERVER_ERROR
     })?;
     headers.insert("x-modified", modified_header_value);
 
     if let Some(prev_file_name) = prev_file_name {
         let prev_file_name_header_value = prev_file_name.parse().map_err(|err| {
-            eprintln!("Failed to parse 'prev-file-name' header value ({prev_file_name:?}): {err}");
+            error!("Failed to parse 'prev-file-name' header value ({prev_file_name:?}): {err}");
             StatusCode::INTERNAL_SERVER_ERROR
         })?;
         headers.insert("x-prev-file", prev_file_name_header_value);
@@ -56,7 +58,7 @@ fn assign_headers(
 
     if let Some(next_file_name) = next_file_name {
         let next_file_name_header_value = next_file_name.parse().map_err(|err| {
-            eprintln!("Failed to parse 'next-file-name' header value ({next_file_name:?}): {err}");
+            error!("Failed to parse 'next-file-name' header value ({next_file_name:?}): {err}");
             StatusCode::INTERNAL_SERVER_ERROR
         })?;
         headers.insert("x-next-file", next_file_name_header_value);
@@ -65,51 +67,105 @@ fn assign_headers(
     Ok(headers)
 }
 
+/// What to respond with for a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Markdown,
+    /// The body rendered as HTML
+    Html,
+    /// The file's headings as JSON
+    Outline,
+}
+
+/// `?format=html` responds with the body rendered as HTML and `?format=outline` with the file's
+/// headings, rather than the raw markdown
+fn format_param(params: &HashMap<String, String>) -> Result<Format, StatusCode> {
+    match params.get("format").map(String::as_str) {
+        None | Some("markdown") => Ok(Format::Markdown),
+        Some("html") => Ok(Format::Html),
+        Some("outline") => Ok(Format::Outline),
+        Some(_) => Err(StatusCode::BAD_REQUEST),
+    }
+}
+
+fn response_body(
+    headers: &mut HeaderMap,
+    response: custard_lib::single::Response<'_>,
+    format: Format,
+) -> Result<String, StatusCode> {
+    match format {
+        Format::Markdown => Ok(response.file.body().to_owned()),
+        Format::Html => {
+            let Some(html) = response.html else {
+                error!("HTML was requested, but custard was built without the 'html' feature");
+                return Err(StatusCode::NOT_IMPLEMENTED);
+            };
+            headers.insert(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("text/html; charset=utf-8"),
+            );
+            Ok(html)
+        }
+        Format::Outline => {
+            let outline = response.file.outline();
+            let outline_string = serde_json::to_string(outline).map_err(|err| {
+                error!(
+                    "Failed to serialize outline ({outline:?}) as JSON during get request: {err}"
+                );
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+            headers.insert(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("application/json"),
+            );
+            Ok(outline_string)
+        }
+    }
+}
+
 fn post_inner(
-    files: &frontmatter_file::keeper::ArcMutex,
+    files: &frontmatter_file::keeper::ArcRwLock,
     params: &HashMap<String, String>,
     name: &str,
-    query_map: FrontmatterQueryMap,
+    query: FrontmatterQuery,
 ) -> Result<(HeaderMap, String), StatusCode> {
-    let keeper = &*lock_keeper(files)?;
+    let keeper = &*files.read();
 
     let order_desc = "desc" == params.get("order").map_or("desc", Deref::deref);
-    let sort_key = params.get("sort").map(Deref::deref);
-    let intersect = params
-        .get("intersect")
-        .map(|p| p.parse())
-        .transpose()
-        .map_err(|_| StatusCode::BAD_REQUEST)?
-        .unwrap_or_default();
+    let nulls_first = nulls_first_param(params)?;
+    let sort = sort_param(params)?;
+    let format = format_param(params)?;
+    let query = apply_intersect_param(params, query)?;
 
     let response = custard_lib::single::single(
         keeper,
         custard_lib::single::Args {
             name,
-            query: Some(FrontmatterQuery {
-                map: query_map,
-                intersect,
-            }),
-            sort_key,
+            query: Some(query),
+            sort,
             order_desc,
+            nulls_first,
+            render_html: format == Format::Html,
+            links: false,
         },
     )
     .ok_or(StatusCode::NOT_FOUND)?;
 
-    let headers = assign_headers(
+    let mut headers = assign_headers(
         response.file,
         response.prev_file_name,
         response.next_file_name,
     )?;
+    let body = response_body(&mut headers, response, format)?;
 
-    Ok((headers, response.file.body().to_owned()))
+    Ok((headers, body))
 }
 
 pub async fn post(
-    State(markdown_files): State<frontmatter_file::keeper::ArcMutex>,
+    State(markdown_files): State<frontmatter_file::keeper::ArcRwLock>,
     params: Query<HashMap<String, String>>,
     Path(name): Path<String>,
-    Json(query): Json<FrontmatterQueryMap>,
+    Json(query): Json<FrontmatterQuery>,
 ) -> Result<(HeaderMap, String), StatusCode> {
     let result = post_inner(&markdown_files, &params, &name, query)?;
 
@@ -117,41 +173,53 @@ pub async fn post(
 }
 
 fn get_inner(
-    files: &frontmatter_file::keeper::ArcMutex,
+    files: &frontmatter_file::keeper::ArcRwLock,
     params: &HashMap<String, String>,
     name: &str,
 ) -> Result<(HeaderMap, String), StatusCode> {
-    let keeper = &*lock_keeper(files)?;
+    let keeper = &*files.read();
 
     let order_desc = "desc" == params.get("order").map_or("desc", Deref::deref);
-    let sort_key = params.get("sort").map(Deref::deref);
+    let nulls_first = nulls_first_param(params)?;
+    let sort = sort_param(params)?;
+    let format = format_param(params)?;
 
     let response = custard_lib::single::single(
         keeper,
         custard_lib::single::Args {
             name,
             query: None,
-            sort_key,
+            sort,
             order_desc,
+            nulls_first,
+            render_html: format == Format::Html,
+            links: false,
         },
     )
     .ok_or(StatusCode::NOT_FOUND)?;
 
-    let headers = assign_headers(
+    let mut headers = assign_headers(
         response.file,
         response.prev_file_name,
         response.next_file_name,
     )?;
+    let body = response_body(&mut headers, response, format)?;
 
-    Ok((headers, response.file.body().to_owned()))
+    Ok((headers, body))
 }
 
+/// Also serves `/frontmatter/file/:name/backlinks`, as a wildcard has to end the route. Served
+/// files always have an extension, so none of them can be called `backlinks`.
 pub async fn get(
-    State(markdown_files): State<frontmatter_file::keeper::ArcMutex>,
+    State(markdown_files): State<frontmatter_file::keeper::ArcRwLock>,
     params: Query<HashMap<String, String>>,
     Path(name): Path<String>,
-) -> Result<(HeaderMap, String), StatusCode> {
+) -> Result<Response, StatusCode> {
+    if let Some(name) = name.strip_suffix("/backlinks") {
+        return Ok(super::links::backlinks(&markdown_files, name)?.into_response());
+    }
+
     let result = get_inner(&markdown_files, &params, &name)?;
 
-    Ok(result)
+    Ok(result.into_response())
 }
@@ -5,26 +5,49 @@ use axum::{
     http::{HeaderMap, StatusCode},
     Json,
 };
-use custard_lib::{frontmatter_file, frontmatter_query::FrontmatterQueryMap};
+use custard_lib::{frontmatter_file, frontmatter_query::FrontmatterQuery};
+use tracing::{error, warn};
 
-use super::lock_keeper;
+use super::{apply_intersect_param, excerpt_param, fields_param, nulls_first_param, sort_param};
 
-fn assign_headers(file_count: usize) -> HeaderMap {
+fn assign_headers(response: &custard_lib::list::Response) -> Result<HeaderMap, StatusCode> {
     let mut headers = HeaderMap::new();
 
-    headers.insert("x-length", file_count.into());
+    headers.insert("x-length", response.total.into());
+
+    if let Some(next_cursor) = &response.next_cursor {
+        let next_cursor_header_value = next_cursor.parse().map_err(|err| {
+            error!("Failed to parse 'next-cursor' header value ({next_cursor:?}): {err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        headers.insert("x-next-cursor", next_cursor_header_value);
+    }
+
+    if let Some(prev_cursor) = &response.prev_cursor {
+        let prev_cursor_header_value = prev_cursor.parse().map_err(|err| {
+            error!("Failed to parse 'prev-cursor' header value ({prev_cursor:?}): {err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        headers.insert("x-prev-cursor", prev_cursor_header_value);
+    }
+
+    Ok(headers)
+}
 
-    headers
+fn map_cursor_error(err: custard_lib::list::CursorError) -> StatusCode {
+    warn!("Failed to use cursor: {err}");
+    StatusCode::BAD_REQUEST
 }
 
 fn get_inner(
     params: &HashMap<String, String>,
-    files: &frontmatter_file::keeper::ArcMutex,
-) -> Result<(HeaderMap, Vec<frontmatter_file::Short>), StatusCode> {
-    let keeper = &*lock_keeper(files)?;
+    files: &frontmatter_file::keeper::ArcRwLock,
+) -> Result<(HeaderMap, Vec<frontmatter_file::Projected>), StatusCode> {
+    let keeper = &*files.read();
 
-    let sort_key = params.get("sort").map(Deref::deref);
+    let sort = sort_param(params)?;
     let order_desc = "desc" == params.get("order").map_or("desc", Deref::deref);
+    let nulls_first = nulls_first_param(params)?;
     let offset = params
         .get("offset")
         .map(|x| x.parse())
@@ -35,21 +58,36 @@ fn get_inner(
         .map(|x| x.parse())
         .transpose()
         .map_err(|_| StatusCode::BAD_REQUEST)?;
+    let after = params.get("after").map(Deref::deref);
+    let before = params.get("before").map(Deref::deref);
+    let fields = fields_param(params);
+    let excerpt = excerpt_param(params)?;
+    let outline = params
+        .get("outline")
+        .map(|x| x.parse())
+        .transpose()
+        .map_err(|_| StatusCode::BAD_REQUEST)?
+        .unwrap_or_default();
 
-    let response = custard_lib::list::get(
+    let response = custard_lib::list::query(
         keeper,
-        custard_lib::list::Get::new(sort_key, order_desc, offset, limit),
-    );
+        custard_lib::list::Args::get(sort, order_desc, nulls_first, offset, limit)
+            .cursors(after, before)
+            .fields(fields)
+            .excerpt(excerpt)
+            .outline(outline),
+    )
+    .map_err(map_cursor_error)?;
 
-    let headers = assign_headers(response.total);
+    let headers = assign_headers(&response)?;
 
     Ok((headers, response.files))
 }
 
 pub async fn get(
-    State(markdown_files): State<frontmatter_file::keeper::ArcMutex>,
+    State(markdown_files): State<frontmatter_file::keeper::ArcRwLock>,
     params: Query<HashMap<String, String>>,
-) -> Result<(HeaderMap, Json<Vec<frontmatter_file::Short>>), StatusCode> {
+) -> Result<(HeaderMap, Json<Vec<frontmatter_file::Projected>>), StatusCode> {
     let (headers, files) = get_inner(&params, &markdown_files)?;
 
     Ok((headers, Json(files)))
@@ -57,13 +95,14 @@ pub async fn get(
 
 fn post_inner(
     params: &HashMap<String, String>,
-    files: &frontmatter_file::keeper::ArcMutex,
-    query: FrontmatterQueryMap,
-) -> Result<(HeaderMap, Vec<frontmatter_file::Short>), StatusCode> {
-    let keeper = &*lock_keeper(files)?;
+    files: &frontmatter_file::keeper::ArcRwLock,
+    query: FrontmatterQuery,
+) -> Result<(HeaderMap, Vec<frontmatter_file::Projected>), StatusCode> {
+    let keeper = &*files.read();
 
-    let sort_key = params.get("sort").map(Deref::deref);
+    let sort = sort_param(params)?;
     let order_desc = "desc" == params.get("order").map_or("desc", Deref::deref);
+    let nulls_first = nulls_first_param(params)?;
     let offset = params
         .get("offset")
         .map(|x| x.parse())
@@ -74,28 +113,38 @@ fn post_inner(
         .map(|x| x.parse())
         .transpose()
         .map_err(|_| StatusCode::BAD_REQUEST)?;
-    let intersect = params
-        .get("intersect")
-        .map(|p| p.parse())
+    let after = params.get("after").map(Deref::deref);
+    let before = params.get("before").map(Deref::deref);
+    let fields = fields_param(params);
+    let excerpt = excerpt_param(params)?;
+    let outline = params
+        .get("outline")
+        .map(|x| x.parse())
         .transpose()
         .map_err(|_| StatusCode::BAD_REQUEST)?
         .unwrap_or_default();
+    let query = apply_intersect_param(params, query)?;
 
     let response = custard_lib::list::query(
         keeper,
-        custard_lib::list::Args::query(query, sort_key, order_desc, offset, limit, intersect),
-    );
+        custard_lib::list::Args::query(query, sort, order_desc, nulls_first, offset, limit)
+            .cursors(after, before)
+            .fields(fields)
+            .excerpt(excerpt)
+            .outline(outline),
+    )
+    .map_err(map_cursor_error)?;
 
-    let headers = assign_headers(response.total);
+    let headers = assign_headers(&response)?;
 
     Ok((headers, response.files))
 }
 
 pub async fn post(
-    State(markdown_files): State<frontmatter_file::keeper::ArcMutex>,
+    State(markdown_files): State<frontmatter_file::keeper::ArcRwLock>,
     params: Query<HashMap<String, String>>,
-    Json(query): Json<FrontmatterQueryMap>,
-) -> Result<(HeaderMap, Json<Vec<frontmatter_file::Short>>), StatusCode> {
+    Json(query): Json<FrontmatterQuery>,
+) -> Result<(HeaderMap, Json<Vec<frontmatter_file::Projected>>), StatusCode> {
     let (headers, files) = post_inner(&params, &markdown_files, query)?;
 
     Ok((headers, Json(files)))
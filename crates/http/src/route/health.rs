@@ -0,0 +1,23 @@
+use axum::{extract::State, http::HeaderMap, Json};
+use custard_lib::{
+    frontmatter_file::{self, keeper::LoadError},
+    health,
+};
+
+fn assign_headers(error_count: usize) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+
+    headers.insert("x-length", error_count.into());
+
+    headers
+}
+
+pub async fn errors(
+    State(markdown_files): State<frontmatter_file::keeper::ArcRwLock>,
+) -> (HeaderMap, Json<Vec<LoadError>>) {
+    let keeper = &*markdown_files.read();
+
+    let errors = health::errors(keeper);
+
+    (assign_headers(errors.len()), Json(errors))
+}
@@ -0,0 +1,42 @@
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    Json,
+};
+use custard_lib::{frontmatter_file, links};
+
+fn assign_headers(length: usize) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+
+    headers.insert("x-length", length.into());
+
+    headers
+}
+
+/// Served from `/frontmatter/file/:name/backlinks` by [`super::frontmatter_file::get`]
+pub fn backlinks(
+    files: &frontmatter_file::keeper::ArcRwLock,
+    name: &str,
+) -> Result<(HeaderMap, Json<Vec<String>>), StatusCode> {
+    let keeper = &*files.read();
+
+    let response = links::links(keeper, links::Args { name }).ok_or(StatusCode::NOT_FOUND)?;
+
+    let backlinks = response
+        .backlinks
+        .into_iter()
+        .map(ToOwned::to_owned)
+        .collect::<Vec<_>>();
+
+    Ok((assign_headers(backlinks.len()), Json(backlinks)))
+}
+
+pub async fn broken(
+    State(markdown_files): State<frontmatter_file::keeper::ArcRwLock>,
+) -> (HeaderMap, Json<Vec<links::BrokenLink>>) {
+    let keeper = &*markdown_files.read();
+
+    let broken = links::broken(keeper);
+
+    (assign_headers(broken.len()), Json(broken))
+}
@@ -1,15 +1,72 @@
 pub mod collate_strings;
 pub mod frontmatter_file;
 pub mod frontmatter_list;
+pub mod health;
+pub mod links;
+pub mod search;
 
-use std::sync::MutexGuard;
+use std::collections::HashMap;
 
 use axum::http::StatusCode;
-use custard_lib::frontmatter_file::{keeper, Keeper};
+use custard_lib::{excerpt::Excerpt, frontmatter_query::FrontmatterQuery, sort::SortSpec};
+use tracing::warn;
 
-fn lock_keeper(keeper: &keeper::ArcMutex) -> Result<MutexGuard<'_, Keeper>, StatusCode> {
-    keeper.lock().map_err(|err| {
-        eprintln!("Failed to lock files data: {err}");
-        StatusCode::INTERNAL_SERVER_ERROR
-    })
+/// `?intersect=true` makes sequences in a plain query map match on any shared element
+fn apply_intersect_param(
+    params: &HashMap<String, String>,
+    query: FrontmatterQuery,
+) -> Result<FrontmatterQuery, StatusCode> {
+    let intersect = params
+        .get("intersect")
+        .map(|p| p.parse())
+        .transpose()
+        .map_err(|_| StatusCode::BAD_REQUEST)?
+        .unwrap_or_default();
+
+    match (intersect, query) {
+        (false, query) => Ok(query),
+        (true, FrontmatterQuery::Subset(map)) => Ok(FrontmatterQuery::Intersect { intersect: map }),
+        (true, _) => Err(StatusCode::BAD_REQUEST),
+    }
+}
+
+/// `?nulls=first` places files without a sort value first, rather than last
+fn nulls_first_param(params: &HashMap<String, String>) -> Result<bool, StatusCode> {
+    match params.get("nulls").map(String::as_str) {
+        None | Some("last") => Ok(false),
+        Some("first") => Ok(true),
+        Some(_) => Err(StatusCode::BAD_REQUEST),
+    }
+}
+
+/// `?sort=pinned:desc,date:desc,title`. Keys without a direction follow `?order`.
+fn sort_param(params: &HashMap<String, String>) -> Result<Vec<SortSpec<'_>>, StatusCode> {
+    params
+        .get("sort")
+        .map(|sort| SortSpec::parse_list(sort))
+        .transpose()
+        .map_err(|err| {
+            warn!("Failed to parse sort parameter: {err}");
+            StatusCode::BAD_REQUEST
+        })
+        .map(Option::unwrap_or_default)
+}
+
+/// `?fields=title,date,name` only includes those fields of each file
+fn fields_param(params: &HashMap<String, String>) -> Option<Vec<&str>> {
+    params
+        .get("fields")
+        .map(|fields| fields.split(',').map(str::trim).collect())
+}
+
+/// `?excerpt=chars:200|words:50|paragraph|more` cuts an excerpt from each file's body
+fn excerpt_param(params: &HashMap<String, String>) -> Result<Option<Excerpt>, StatusCode> {
+    params
+        .get("excerpt")
+        .map(|excerpt| excerpt.parse())
+        .transpose()
+        .map_err(|err| {
+            warn!("Failed to parse excerpt parameter: {err}");
+            StatusCode::BAD_REQUEST
+        })
 }
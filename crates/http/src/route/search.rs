@@ -0,0 +1,72 @@
+use std::collections::HashMap;
+
+use axum::{
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+    Json,
+};
+use custard_lib::{frontmatter_file, frontmatter_query::FrontmatterQuery, search};
+
+use super::apply_intersect_param;
+
+fn assign_headers(hit_count: usize) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+
+    headers.insert("x-length", hit_count.into());
+
+    headers
+}
+
+fn search_inner(
+    params: &HashMap<String, String>,
+    files: &frontmatter_file::keeper::ArcRwLock,
+    query: Option<FrontmatterQuery>,
+) -> Result<(HeaderMap, Vec<search::Hit>), StatusCode> {
+    let keeper = &*files.read();
+
+    let text = params.get("q").ok_or(StatusCode::BAD_REQUEST)?;
+    let offset = params
+        .get("offset")
+        .map(|x| x.parse())
+        .transpose()
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    let limit = params
+        .get("limit")
+        .map(|x| x.parse())
+        .transpose()
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let response = search::search(
+        keeper,
+        search::Args {
+            text,
+            query,
+            offset,
+            limit,
+        },
+    );
+
+    let headers = assign_headers(response.total);
+
+    Ok((headers, response.hits))
+}
+
+pub async fn get(
+    State(markdown_files): State<frontmatter_file::keeper::ArcRwLock>,
+    params: Query<HashMap<String, String>>,
+) -> Result<(HeaderMap, Json<Vec<search::Hit>>), StatusCode> {
+    let (headers, hits) = search_inner(&params, &markdown_files, None)?;
+
+    Ok((headers, Json(hits)))
+}
+
+pub async fn post(
+    State(markdown_files): State<frontmatter_file::keeper::ArcRwLock>,
+    params: Query<HashMap<String, String>>,
+    Json(query): Json<FrontmatterQuery>,
+) -> Result<(HeaderMap, Json<Vec<search::Hit>>), StatusCode> {
+    let query = apply_intersect_param(&params, query)?;
+    let (headers, hits) = search_inner(&params, &markdown_files, Some(query))?;
+
+    Ok((headers, Json(hits)))
+}
@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+
+use camino::Utf8PathBuf;
+use chrono::TimeZone;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use custard_lib::{
+    frontmatter_file::{FrontmatterFile, JsonFrontmatter, Keeper},
+    frontmatter_query::FrontmatterQuery,
+    list,
+    sort::SortSpec,
+};
+
+const FILE_COUNT: usize = 5_000;
+
+fn make_keeper() -> Keeper {
+    let dt = chrono::Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+    let files = (0..FILE_COUNT)
+        .map(|i| {
+            let name = format!("note-{i}.md");
+            let frontmatter = serde_yaml::from_str(&format!(
+                "title: Note {i}\ndate: 2024-{:02}-{:02}\nrating: {}\ntags: [tag-{}, tag-{}]\nauthor:\n  handle: author-{}",
+                i % 12 + 1,
+                i % 28 + 1,
+                i % 5,
+                i % 7,
+                i % 11,
+                i % 3,
+            ))
+            .unwrap();
+            let file = FrontmatterFile::new(
+                name.clone(),
+                Some(frontmatter),
+                format!("# Note {i}\n\nSome text.\n"),
+                dt,
+                dt,
+            );
+            (Utf8PathBuf::from(format!("/{name}")), file)
+        })
+        .collect::<HashMap<_, _>>();
+    Keeper::from_files(Utf8PathBuf::from("/"), files)
+}
+
+fn query(json: serde_json::Value) -> FrontmatterQuery {
+    serde_json::from_value(json).unwrap()
+}
+
+/// Range queries can't use the frontmatter index, so every file's frontmatter is looked at
+fn range_query() -> FrontmatterQuery {
+    query(serde_json::json!({ "rating": { "$gte": 3 }, "author.handle": "author-1" }))
+}
+
+fn list(c: &mut Criterion) {
+    let keeper = make_keeper();
+    let mut group = c.benchmark_group("list");
+
+    group.bench_function("range", |b| {
+        b.iter(|| {
+            list::query(
+                &keeper,
+                list::Args::query(range_query(), vec![], false, false, None, Some(20)),
+            )
+            .unwrap()
+        });
+    });
+
+    group.bench_function("indexed", |b| {
+        b.iter(|| {
+            let query = query(serde_json::json!({ "tags": ["tag-3"], "rating": 4 }));
+            list::query(
+                &keeper,
+                list::Args::query(query, vec![], false, false, None, Some(20)),
+            )
+            .unwrap()
+        });
+    });
+
+    group.bench_function("sorted", |b| {
+        let sort = SortSpec::parse_list("rating:desc,date").unwrap();
+        b.iter(|| {
+            list::query(
+                &keeper,
+                list::Args::get(black_box(sort.clone()), false, false, None, Some(20)),
+            )
+            .unwrap()
+        });
+    });
+
+    group.finish();
+}
+
+/// Matching every file against a query, with the frontmatter converted to JSON each time as it
+/// used to be, and with the JSON cached when the file was loaded
+fn matching(c: &mut Criterion) {
+    let keeper = make_keeper();
+    let query = range_query();
+    let mut group = c.benchmark_group("match");
+
+    group.bench_function("converted", |b| {
+        b.iter(|| {
+            keeper
+                .files()
+                .filter(|file| {
+                    let json = file.frontmatter().map(|frontmatter| {
+                        serde_json::from_value::<JsonFrontmatter>(
+                            serde_json::to_value(frontmatter).unwrap(),
+                        )
+                        .unwrap()
+                    });
+                    query.matches(black_box(json.as_ref()))
+                })
+                .count()
+        });
+    });
+
+    group.bench_function("cached", |b| {
+        b.iter(|| {
+            keeper
+                .files()
+                .filter(|file| query.matches(black_box(file.json_frontmatter())))
+                .count()
+        });
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, list, matching);
+criterion_main!(benches);
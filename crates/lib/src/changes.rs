@@ -0,0 +1,319 @@
+use std::time::{Duration, Instant};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+use crate::frontmatter_file::{FrontmatterFile, Short};
+use crate::frontmatter_query::FrontmatterQuery;
+
+/// How many changes a subscriber can fall behind by before it starts missing them
+const CAPACITY: usize = 256;
+
+/// How long to wait for the second half of a rename. `notify` delivers both halves together when
+/// it sees both, so this only delays files moved out of the watched directory.
+pub(crate) const RENAME_WAIT: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Args {
+    /// Only report changes to files that match, before or after the change
+    #[serde(default)]
+    pub query: Option<FrontmatterQuery>,
+}
+
+/// A file as it was before a change and as it is after, either of which may not exist
+#[derive(Debug, Clone)]
+pub struct Change {
+    before: Option<Short>,
+    after: Option<Short>,
+}
+
+/// A [`Change`] as a subscriber is told about it
+#[derive(Debug, Serialize, PartialEq, Eq)]
+#[serde(tag = "tag", content = "value")]
+pub enum Event {
+    Created(Short),
+    Edited(Short),
+    Renamed {
+        from: String,
+        file: Short,
+    },
+    Deleted(String),
+    /// The subscriber fell behind and missed this many changes
+    Lagged(u64),
+}
+
+impl Change {
+    /// How the change looks to a subscriber that only wants files matching `query`. A file that
+    /// starts or stops matching is reported as created or deleted, and `None` is returned if it
+    /// never matched.
+    #[must_use]
+    pub fn into_event(self, query: Option<&FrontmatterQuery>) -> Option<Event> {
+        let matches =
+            |file: &Short| query.is_none_or(|query| query.matches(file.json_frontmatter()));
+        let before = self.before.filter(|file| matches(file));
+        let after = self.after.filter(|file| matches(file));
+        match (before, after) {
+            (None, None) => None,
+            (Some(before), None) => Some(Event::Deleted(before.name)),
+            (None, Some(after)) => Some(Event::Created(after)),
+            (Some(before), Some(after)) if before.name != after.name => Some(Event::Renamed {
+                from: before.name,
+                file: after,
+            }),
+            (Some(_), Some(after)) => Some(Event::Edited(after)),
+        }
+    }
+}
+
+/// Files removed by the first half of a rename
+#[derive(Debug)]
+struct RenamedFrom {
+    tracker: Option<usize>,
+    /// When to stop waiting for the other half and report the files as deleted
+    deadline: Instant,
+    path: Utf8PathBuf,
+    removed: Vec<(Utf8PathBuf, Change)>,
+}
+
+/// Collects the changes made while handling a watch event, to be sent to subscribers once it's
+/// handled. Nothing is collected while there aren't any subscribers.
+#[derive(Debug)]
+pub(crate) struct Recorder {
+    sender: broadcast::Sender<Change>,
+    pending: Vec<(Utf8PathBuf, Change)>,
+    renamed_from: Option<RenamedFrom>,
+}
+
+impl Default for Recorder {
+    fn default() -> Self {
+        Self {
+            sender: broadcast::channel(CAPACITY).0,
+            pending: Vec::new(),
+            renamed_from: None,
+        }
+    }
+}
+
+impl Recorder {
+    pub(crate) fn subscribe(&self) -> broadcast::Receiver<Change> {
+        self.sender.subscribe()
+    }
+
+    pub(crate) fn is_subscribed(&self) -> bool {
+        self.sender.receiver_count() > 0
+    }
+
+    pub(crate) fn record(
+        &mut self,
+        path: &Utf8Path,
+        before: Option<&FrontmatterFile>,
+        after: Option<&FrontmatterFile>,
+    ) {
+        if !self.is_subscribed() || before == after {
+            return;
+        }
+        let change = Change {
+            before: before.cloned().map(Short::from),
+            after: after.cloned().map(Short::from),
+        };
+        self.pending.push((path.to_owned(), change));
+    }
+
+    /// Holds back what was just removed from `path`, in case it turns up elsewhere and can be
+    /// reported as renamed
+    pub(crate) fn hold(&mut self, tracker: Option<usize>, path: &Utf8Path) {
+        self.release();
+        self.renamed_from = Some(RenamedFrom {
+            tracker,
+            deadline: Instant::now() + RENAME_WAIT,
+            path: path.to_owned(),
+            removed: std::mem::take(&mut self.pending),
+        });
+    }
+
+    /// Reports the files held back by [`Recorder::hold`] as deleted, straight away
+    pub(crate) fn release(&mut self) {
+        if let Some(renamed_from) = self.renamed_from.take() {
+            self.send(renamed_from.removed);
+        }
+    }
+
+    /// Like [`Recorder::release`], but only once what's held back has waited until its deadline.
+    /// Gives the deadline if it's still waiting.
+    pub(crate) fn release_expired(&mut self, now: Instant) -> Option<Instant> {
+        let deadline = self.renamed_from.as_ref()?.deadline;
+        if deadline > now {
+            return Some(deadline);
+        }
+        self.release();
+        None
+    }
+
+    /// Pairs the files just added under `to` with the files held back from the same rename, by
+    /// their path beneath the renamed file or directory
+    pub(crate) fn pair(&mut self, tracker: Option<usize>, to: &Utf8Path) {
+        let Some(RenamedFrom {
+            path: from,
+            mut removed,
+            ..
+        }) = self
+            .renamed_from
+            .take_if(|renamed_from| renamed_from.tracker == tracker)
+        else {
+            self.release();
+            return;
+        };
+        for (path, change) in &mut self.pending {
+            let Ok(relative) = path.strip_prefix(to) else {
+                continue;
+            };
+            let Some(i) = removed
+                .iter()
+                .position(|(path, _)| path.strip_prefix(&from).ok() == Some(relative))
+            else {
+                continue;
+            };
+            let (_, removal) = removed.swap_remove(i);
+            change.before = removal.before;
+        }
+        self.pending.splice(0..0, removed);
+    }
+
+    /// Sends every change collected so far
+    pub(crate) fn publish(&mut self) {
+        let pending = std::mem::take(&mut self.pending);
+        self.send(pending);
+    }
+
+    fn send(&self, changes: Vec<(Utf8PathBuf, Change)>) {
+        for (_, change) in changes {
+            // Fails only if everyone has unsubscribed since
+            let _ = self.sender.send(change);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Instant;
+
+    use camino::Utf8Path;
+    use chrono::TimeZone;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+    use tokio::sync::broadcast;
+
+    use super::{Change, Event, Recorder};
+    use crate::{frontmatter_file::FrontmatterFile, frontmatter_query::FrontmatterQuery};
+
+    fn file(name: &str, frontmatter: &str) -> FrontmatterFile {
+        let dt = chrono::Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        FrontmatterFile::new(
+            name.to_owned(),
+            Some(serde_yaml::from_str(frontmatter).unwrap()),
+            "Body\n".to_owned(),
+            dt,
+            dt,
+        )
+    }
+
+    fn events(
+        recorder: &mut Recorder,
+        changes: &mut broadcast::Receiver<Change>,
+        query: Option<&FrontmatterQuery>,
+    ) -> Vec<Event> {
+        recorder.publish();
+        std::iter::from_fn(|| changes.try_recv().ok())
+            .filter_map(|change| change.into_event(query))
+            .collect()
+    }
+
+    #[test]
+    fn into_event() {
+        let draft = file("a.md", "draft: true");
+        let published = file("a.md", "draft: false");
+        let mut recorder = Recorder::default();
+        let mut changes = recorder.subscribe();
+        let mut published_changes = recorder.subscribe();
+        recorder.record(Utf8Path::new("/a.md"), None, Some(&draft));
+        recorder.record(Utf8Path::new("/a.md"), Some(&draft), Some(&published));
+        recorder.record(Utf8Path::new("/a.md"), Some(&published), None);
+        recorder.record(Utf8Path::new("/a.md"), Some(&draft), Some(&draft));
+        assert_eq!(3, recorder.pending.len(), "unchanged files aren't changes");
+
+        assert_eq!(
+            vec![
+                Event::Created(draft.into()),
+                Event::Edited(published.clone().into()),
+                Event::Deleted("a.md".to_owned()),
+            ],
+            events(&mut recorder, &mut changes, None)
+        );
+        let query = serde_json::from_value(json!({ "draft": false })).unwrap();
+        assert_eq!(
+            vec![
+                Event::Created(published.into()),
+                Event::Deleted("a.md".to_owned()),
+            ],
+            events(&mut recorder, &mut published_changes, Some(&query)),
+            "files moving in and out of the query are created and deleted"
+        );
+    }
+
+    #[test]
+    fn pair() {
+        let a = file("notes/a.md", "draft: true");
+        let b = file("notes/b.md", "draft: false");
+        let moved_a = file("archive/a.md", "draft: true");
+        let mut recorder = Recorder::default();
+        let mut changes = recorder.subscribe();
+
+        recorder.record(Utf8Path::new("/notes/a.md"), Some(&a), None);
+        recorder.record(Utf8Path::new("/notes/b.md"), Some(&b), None);
+        recorder.hold(Some(1), Utf8Path::new("/notes"));
+        recorder.record(Utf8Path::new("/archive/a.md"), None, Some(&moved_a));
+        recorder.pair(Some(1), Utf8Path::new("/archive"));
+        assert_eq!(
+            vec![
+                Event::Deleted("notes/b.md".to_owned()),
+                Event::Renamed {
+                    from: "notes/a.md".to_owned(),
+                    file: moved_a.clone().into(),
+                },
+            ],
+            events(&mut recorder, &mut changes, None)
+        );
+
+        recorder.record(Utf8Path::new("/notes/a.md"), Some(&a), None);
+        recorder.hold(Some(2), Utf8Path::new("/notes/a.md"));
+        assert_eq!(
+            Vec::<Event>::new(),
+            events(&mut recorder, &mut changes, None)
+        );
+        recorder.record(Utf8Path::new("/archive/a.md"), None, Some(&moved_a));
+        recorder.pair(Some(3), Utf8Path::new("/archive/a.md"));
+        assert_eq!(
+            vec![
+                Event::Deleted("notes/a.md".to_owned()),
+                Event::Created(moved_a.into()),
+            ],
+            events(&mut recorder, &mut changes, None),
+            "halves of different renames aren't paired"
+        );
+
+        recorder.record(Utf8Path::new("/notes/a.md"), Some(&a), None);
+        recorder.hold(Some(4), Utf8Path::new("/notes/a.md"));
+        let deadline = recorder.release_expired(Instant::now());
+        assert!(deadline.is_some(), "held until the other half can turn up");
+        assert_eq!(
+            Vec::<Event>::new(),
+            events(&mut recorder, &mut changes, None)
+        );
+        assert_eq!(None, recorder.release_expired(deadline.unwrap()));
+        assert_eq!(
+            vec![Event::Deleted("notes/a.md".to_owned())],
+            events(&mut recorder, &mut changes, None)
+        );
+    }
+}
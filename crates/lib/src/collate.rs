@@ -6,6 +6,7 @@ use super::query_files;
 use crate::{
     frontmatter_file::{FrontmatterFile, Keeper},
     frontmatter_query::FrontmatterQuery,
+    key_path,
 };
 
 fn collate_strings_from_files<'a>(
@@ -14,7 +15,7 @@ fn collate_strings_from_files<'a>(
 ) -> Vec<String> {
     files
         .filter_map(|fmf| fmf.frontmatter())
-        .filter_map(|fm| fm.get(key))
+        .filter_map(|fm| key_path::get_yaml(fm, key))
         .filter_map(|v| match v {
             serde_yaml::Value::String(v) => Some(vec![v.clone()]),
             serde_yaml::Value::Sequence(seq) => seq
@@ -38,13 +39,11 @@ pub struct Args<'a> {
 }
 
 fn inner(keeper: &Keeper, args: Args<'_>) -> Vec<String> {
-    let files = keeper.files();
-
     let mut values = if let Some(query) = args.query {
-        let files = query_files(files, query, None);
+        let files = query_files(keeper, query, None);
         collate_strings_from_files(files, args.key)
     } else {
-        collate_strings_from_files(files, args.key)
+        collate_strings_from_files(keeper.files(), args.key)
     };
 
     values.sort();
@@ -0,0 +1,280 @@
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::Deserialize;
+use tracing::level_filters::{LevelFilter, ParseLevelFilterError};
+
+use crate::{
+    filter::{Filter, NewFilterError},
+    frontmatter_file::keeper::Options,
+};
+
+/// The config file looked for in the served directory when one isn't given
+pub const FILE_NAME: &str = "custard.toml";
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReadConfigError {
+    #[error("Failed to read config file ({0}): {1}")]
+    Io(Utf8PathBuf, std::io::Error),
+    #[error("Failed to parse config file ({0}): {1}")]
+    Toml(Utf8PathBuf, toml::de::Error),
+    #[error("Invalid collection name ({0}): only letters, digits, '-' and '_' are allowed")]
+    CollectionName(String),
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid log_level in config ({0}): {1}")]
+pub struct InvalidLogLevel(String, ParseLevelFilterError);
+
+/// Settings for `custard` and `custard-sock`, which can share one `custard.toml`. Command-line
+/// arguments take precedence over all of these.
+#[derive(Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The directory to serve. A relative path is relative to the config file.
+    pub dir: Option<Utf8PathBuf>,
+    /// `error`, `warn`, `info`, `debug`, `trace` or `off`
+    pub log_level: Option<String>,
+    /// Refuse to start if any file's frontmatter doesn't parse, rather than serving it without
+    pub strict: bool,
+    /// Extensions of the files to serve. Defaults to `["md"]`.
+    pub extensions: Option<Vec<String>>,
+    /// Gitignore-style patterns of files and directories not to serve
+    pub ignore: Vec<String>,
+    /// Frontmatter keys to index for faster queries, which may be nested like `author.handle`.
+    /// Defaults to every top-level key.
+    pub index_keys: Option<Vec<String>>,
+    /// Further directories to serve, each with its own files, by name
+    pub collections: BTreeMap<String, Collection>,
+    pub http: Http,
+    pub sock: Sock,
+}
+
+/// A directory served alongside the main one. Anything left out is taken from the top level.
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct Collection {
+    /// A relative path is relative to the config file
+    pub dir: Utf8PathBuf,
+    pub strict: Option<bool>,
+    pub extensions: Option<Vec<String>>,
+    pub ignore: Option<Vec<String>>,
+    pub index_keys: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Http {
+    pub port: Option<u16>,
+    /// The IP address to listen on. Defaults to `0.0.0.0`.
+    pub bind: Option<IpAddr>,
+}
+
+#[derive(Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Sock {
+    /// A relative path is relative to the config file
+    pub socket: Option<Utf8PathBuf>,
+}
+
+impl Config {
+    pub fn read(path: &Utf8Path) -> Result<Self, ReadConfigError> {
+        // Relative paths inside stay correct if the working directory changes
+        let path = &path
+            .canonicalize_utf8()
+            .map_err(|err| ReadConfigError::Io(path.to_owned(), err))?;
+        let string = std::fs::read_to_string(path)
+            .map_err(|err| ReadConfigError::Io(path.to_owned(), err))?;
+        let mut config = toml::from_str::<Self>(&string)
+            .map_err(|err| ReadConfigError::Toml(path.to_owned(), err))?;
+        let base = path.parent().unwrap_or(Utf8Path::new(""));
+        config.dir = config.dir.map(|dir| base.join(dir));
+        config.sock.socket = config.sock.socket.map(|socket| base.join(socket));
+        for (name, collection) in &mut config.collections {
+            let is_valid = !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+            if !is_valid {
+                return Err(ReadConfigError::CollectionName(name.clone()));
+            }
+            collection.dir = base.join(&collection.dir);
+        }
+        Ok(config)
+    }
+
+    /// Reads `path` if one is given, otherwise [`FILE_NAME`] in `dir` if it exists. Without
+    /// either, everything is left to its default.
+    pub fn find(path: Option<&Utf8Path>, dir: &Utf8Path) -> Result<Self, ReadConfigError> {
+        if let Some(path) = path {
+            return Self::read(path);
+        }
+        let path = dir.join(FILE_NAME);
+        if path.is_file() {
+            Self::read(&path)
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    /// `cli` if it's given, otherwise the configured level, otherwise `info`
+    pub fn log_level(&self, cli: Option<LevelFilter>) -> Result<LevelFilter, InvalidLogLevel> {
+        match (cli, &self.log_level) {
+            (Some(level), _) => Ok(level),
+            (None, Some(level)) => level
+                .parse()
+                .map_err(|err| InvalidLogLevel(level.clone(), err)),
+            (None, None) => Ok(LevelFilter::INFO),
+        }
+    }
+
+    pub fn keeper_options(&self) -> Result<Options, NewFilterError> {
+        options(
+            self.strict,
+            self.extensions.as_deref(),
+            &self.ignore,
+            self.index_keys.as_ref(),
+        )
+    }
+
+    pub fn collection_options(&self, collection: &Collection) -> Result<Options, NewFilterError> {
+        options(
+            collection.strict.unwrap_or(self.strict),
+            collection
+                .extensions
+                .as_deref()
+                .or(self.extensions.as_deref()),
+            collection.ignore.as_deref().unwrap_or(&self.ignore),
+            collection.index_keys.as_ref().or(self.index_keys.as_ref()),
+        )
+    }
+}
+
+fn options(
+    strict: bool,
+    extensions: Option<&[String]>,
+    ignore: &[String],
+    index_keys: Option<&Vec<String>>,
+) -> Result<Options, NewFilterError> {
+    let filter = match extensions {
+        Some(extensions) => Filter::new(extensions, ignore)?,
+        None => Filter::new(["md"], ignore)?,
+    };
+    Ok(Options {
+        lenient: !strict,
+        filter,
+        index_keys: index_keys.cloned(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::Ipv6Addr;
+
+    use camino::Utf8PathBuf;
+    use pretty_assertions::assert_eq;
+    use tracing::level_filters::LevelFilter;
+
+    use super::{Collection, Config, Http, Sock};
+
+    #[test]
+    fn read() {
+        let dir = Utf8PathBuf::try_from(std::env::temp_dir().join("custard_config_read")).unwrap();
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("custard.toml");
+        std::fs::write(
+            &path,
+            r#"
+dir = "notes"
+log_level = "debug"
+extensions = ["md", "markdown"]
+ignore = ["_drafts/", ".*"]
+
+[collections.blog]
+dir = "/srv/blog"
+ignore = []
+
+[http]
+port = 3000
+bind = "::1"
+
+[sock]
+socket = "/tmp/custard.sock"
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            Config {
+                dir: Some(dir.join("notes")),
+                log_level: Some("debug".to_owned()),
+                strict: false,
+                extensions: Some(vec!["md".to_owned(), "markdown".to_owned()]),
+                ignore: vec!["_drafts/".to_owned(), ".*".to_owned()],
+                index_keys: None,
+                collections: [(
+                    "blog".to_owned(),
+                    Collection {
+                        dir: Utf8PathBuf::from("/srv/blog"),
+                        strict: None,
+                        extensions: None,
+                        ignore: Some(vec![]),
+                        index_keys: None,
+                    }
+                )]
+                .into(),
+                http: Http {
+                    port: Some(3000),
+                    bind: Some(Ipv6Addr::LOCALHOST.into()),
+                },
+                sock: Sock {
+                    socket: Some(Utf8PathBuf::from("/tmp/custard.sock")),
+                },
+            },
+            Config::find(None, &dir).unwrap()
+        );
+
+        std::fs::write(&path, "[http]\nbind = \"localhost\"\n").unwrap();
+        assert!(
+            Config::read(&path).is_err(),
+            "only IP addresses are bound to"
+        );
+
+        std::fs::write(&path, "extension = [\"md\"]\n").unwrap();
+        assert!(Config::read(&path).is_err(), "unknown keys are mistakes");
+
+        std::fs::write(&path, "[collections.\"a/b\"]\ndir = \"a\"\n").unwrap();
+        assert!(
+            Config::read(&path).is_err(),
+            "collection names are used in paths"
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(Config::default(), Config::find(None, &dir).unwrap());
+        assert!(Config::find(Some(&path), &dir).is_err());
+    }
+
+    #[test]
+    fn log_level() {
+        let config = Config {
+            log_level: Some("debug".to_owned()),
+            ..Config::default()
+        };
+        assert_eq!(
+            LevelFilter::WARN,
+            config.log_level(Some(LevelFilter::WARN)).unwrap()
+        );
+        assert_eq!(LevelFilter::DEBUG, config.log_level(None).unwrap());
+        assert_eq!(
+            LevelFilter::INFO,
+            Config::default().log_level(None).unwrap()
+        );
+
+        let config = Config {
+            log_level: Some("loud".to_owned()),
+            ..Config::default()
+        };
+        assert!(config.log_level(None).is_err());
+    }
+}
@@ -0,0 +1,197 @@
+use std::str::FromStr;
+
+use pulldown_cmark::{Event, Tag, TagEnd};
+use serde::Deserialize;
+
+use crate::markup;
+
+const MORE_MARKER: &str = "<!-- more -->";
+
+/// How to cut an excerpt from a file's body. Markdown syntax is always stripped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Excerpt {
+    /// At most this many characters, cut back to a word boundary
+    Chars(usize),
+    /// At most this many words
+    Words(usize),
+    /// The first paragraph, skipping headings
+    Paragraph,
+    /// Everything before a `<!-- more -->` marker, or the first paragraph if there isn't one
+    More,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid excerpt '{0}'. Expected one of 'chars:<n>', 'words:<n>', 'paragraph' or 'more'")]
+pub struct ParseExcerptError(String);
+
+impl FromStr for Excerpt {
+    type Err = ParseExcerptError;
+
+    /// Parses `chars:200`, `words:50`, `paragraph` or `more`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ParseExcerptError(s.to_owned());
+        let count = |n: &str| n.trim().parse().map_err(|_| err());
+        match s.trim().split_once(':') {
+            Some(("chars", n)) => Ok(Self::Chars(count(n)?)),
+            Some(("words", n)) => Ok(Self::Words(count(n)?)),
+            None if s.trim() == "paragraph" => Ok(Self::Paragraph),
+            None if s.trim() == "more" => Ok(Self::More),
+            _ => Err(err()),
+        }
+    }
+}
+
+struct Block {
+    heading: bool,
+    text: String,
+}
+
+/// The plain text of each block of prose in `markdown`. Code and HTML blocks are left out.
+fn blocks(markdown: &str) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut text = String::new();
+    let mut skipping = 0usize;
+
+    let mut flush = |text: &mut String, heading: bool| {
+        let trimmed = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        if !trimmed.is_empty() {
+            blocks.push(Block {
+                heading,
+                text: trimmed,
+            });
+        }
+        text.clear();
+    };
+
+    for event in markup::markdown_parser(markdown) {
+        match event {
+            Event::Start(Tag::CodeBlock(_) | Tag::HtmlBlock) => skipping += 1,
+            Event::End(TagEnd::CodeBlock | TagEnd::HtmlBlock) => {
+                skipping = skipping.saturating_sub(1);
+            }
+            _ if skipping > 0 => {}
+            Event::Text(t) | Event::Code(t) => text.push_str(&t),
+            Event::SoftBreak | Event::HardBreak => text.push(' '),
+            Event::End(TagEnd::Heading(_)) => flush(&mut text, true),
+            Event::Start(Tag::Item) | Event::End(TagEnd::Paragraph | TagEnd::Item) => {
+                flush(&mut text, false);
+            }
+            _ => {}
+        }
+    }
+    flush(&mut text, false);
+
+    blocks
+}
+
+fn first_paragraph(markdown: &str) -> Option<String> {
+    blocks(markdown)
+        .into_iter()
+        .find(|block| !block.heading)
+        .map(|block| block.text)
+}
+
+fn plain_text(markdown: &str) -> String {
+    blocks(markdown)
+        .into_iter()
+        .map(|block| block.text)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+impl Excerpt {
+    /// Cuts an excerpt from `body`, if it has any prose
+    #[must_use]
+    pub fn cut(self, body: &str) -> Option<String> {
+        let excerpt = match self {
+            Self::Chars(count) => {
+                let text = plain_text(body);
+                match text.char_indices().nth(count) {
+                    None => text,
+                    Some((end, c)) => {
+                        let cut = &text[..end];
+                        let cut = if c.is_whitespace() {
+                            cut
+                        } else {
+                            cut.rsplit_once(char::is_whitespace)
+                                .map_or(cut, |(cut, _)| cut)
+                        };
+                        format!("{} …", cut.trim_end())
+                    }
+                }
+            }
+            Self::Words(count) => {
+                let text = plain_text(body);
+                let words = text.split_whitespace().collect::<Vec<_>>();
+                if words.len() > count {
+                    format!("{} …", words[..count].join(" "))
+                } else {
+                    text
+                }
+            }
+            Self::Paragraph => first_paragraph(body)?,
+            Self::More => match body.split_once(MORE_MARKER) {
+                Some((before, _)) => plain_text(before),
+                None => first_paragraph(body)?,
+            },
+        };
+        (!excerpt.is_empty()).then_some(excerpt)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::Excerpt;
+
+    const BODY: &str = "# Heading
+
+The *first* paragraph, with a [link](https://example.com)
+and `code`.
+
+```
+let skipped = true;
+```
+
+- one
+- two
+
+<!-- more -->
+
+After the fold.
+";
+
+    #[test]
+    fn strategies() {
+        assert_eq!(
+            Some("The first paragraph, with a link and code.".to_owned()),
+            Excerpt::Paragraph.cut(BODY)
+        );
+        assert_eq!(
+            Some("Heading The first paragraph, with a link and code. one two".to_owned()),
+            Excerpt::More.cut(BODY)
+        );
+        assert_eq!(
+            Some("Heading The first …".to_owned()),
+            Excerpt::Chars(20).cut(BODY)
+        );
+        assert_eq!(
+            Some("Heading The first paragraph, …".to_owned()),
+            Excerpt::Words(4).cut(BODY)
+        );
+        assert_eq!(Some("Short".to_owned()), Excerpt::Words(4).cut("Short"));
+        assert_eq!(None, Excerpt::Paragraph.cut("# Only a heading"));
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(Excerpt::Chars(200), "chars:200".parse().unwrap());
+        assert_eq!(Excerpt::Words(50), "words:50".parse().unwrap());
+        assert_eq!(Excerpt::Paragraph, "paragraph".parse().unwrap());
+        assert_eq!(Excerpt::More, "more".parse().unwrap());
+        assert!("chars".parse::<Excerpt>().is_err());
+        assert!("lines:3".parse::<Excerpt>().is_err());
+    }
+}
@@ -0,0 +1,123 @@
+use camino::Utf8Path;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+
+#[derive(Debug, thiserror::Error)]
+pub enum NewFilterError {
+    #[error("Invalid ignore pattern ({0}): {1}")]
+    Pattern(String, ignore::Error),
+    #[error("Failed to build ignore patterns: {0}")]
+    Build(ignore::Error),
+}
+
+/// Which files under the root are served: those with one of `extensions`, unless they, or a
+/// directory they're in, match one of the gitignore-style `ignore` patterns
+#[derive(Debug, Clone)]
+pub struct Filter {
+    extensions: Vec<String>,
+    ignore: Gitignore,
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Self {
+            extensions: vec!["md".to_owned()],
+            ignore: Gitignore::empty(),
+        }
+    }
+}
+
+impl Filter {
+    /// Extensions may be given with or without a leading `.`. Patterns are matched against paths
+    /// relative to the root, as if they were lines of a `.gitignore` there.
+    pub fn new<E, I>(extensions: E, ignore: I) -> Result<Self, NewFilterError>
+    where
+        E: IntoIterator,
+        E::Item: AsRef<str>,
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let extensions = extensions
+            .into_iter()
+            .map(|ext| ext.as_ref().trim_start_matches('.').to_owned())
+            .collect();
+        let mut builder = GitignoreBuilder::new("");
+        for pattern in ignore {
+            let pattern = pattern.as_ref();
+            builder
+                .add_line(None, pattern)
+                .map_err(|err| NewFilterError::Pattern(pattern.to_owned(), err))?;
+        }
+        let ignore = builder.build().map_err(NewFilterError::Build)?;
+        Ok(Self { extensions, ignore })
+    }
+
+    /// The extensions of the files served, without a leading `.`
+    pub(crate) fn extensions(&self) -> &[String] {
+        &self.extensions
+    }
+
+    fn is_ignored(&self, relative: &Utf8Path, is_dir: bool) -> bool {
+        self.ignore
+            .matched_path_or_any_parents(relative, is_dir)
+            .is_ignore()
+    }
+
+    /// Whether the file at `path`, beneath `root`, should be served
+    pub(crate) fn includes_file(&self, root: &Utf8Path, path: &Utf8Path) -> bool {
+        path.extension()
+            .is_some_and(|ext| self.extensions.iter().any(|extension| extension == ext))
+            && path
+                .strip_prefix(root)
+                .is_ok_and(|relative| !self.is_ignored(relative, false))
+    }
+
+    /// Whether files in the directory at `path`, beneath `root`, may be served
+    pub(crate) fn includes_dir(&self, root: &Utf8Path, path: &Utf8Path) -> bool {
+        match path.strip_prefix(root) {
+            Ok(relative) if relative.as_str().is_empty() => true,
+            Ok(relative) => !self.is_ignored(relative, true),
+            Err(_) => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use camino::Utf8Path;
+
+    use super::Filter;
+
+    #[test]
+    fn includes() {
+        let root = Utf8Path::new("/notes");
+        let filter = Filter::new(
+            [".md", "markdown", "txt"],
+            ["_drafts/", ".*", "*.swp", "!.well-known/"],
+        )
+        .unwrap();
+
+        let included = |path: &str| filter.includes_file(root, &root.join(path));
+        assert!(included("a.md"));
+        assert!(included("posts/b.markdown"));
+        assert!(included("c.txt"));
+        assert!(
+            included(".well-known/d.md"),
+            "negated patterns are respected"
+        );
+        assert!(!included("e.mdx"), "unlisted extension");
+        assert!(!included("_drafts/f.md"));
+        assert!(!included("posts/_drafts/nested/g.md"));
+        assert!(!included(".hidden.md"));
+        assert!(!included(".obsidian/h.md"));
+        assert!(!included("i.md.swp"));
+        assert!(!filter.includes_file(root, Utf8Path::new("/elsewhere/j.md")));
+
+        assert!(filter.includes_dir(root, root));
+        assert!(filter.includes_dir(root, &root.join("posts")));
+        assert!(!filter.includes_dir(root, &root.join("posts/_drafts")));
+
+        let default = Filter::default();
+        assert!(default.includes_file(root, &root.join(".hidden.md")));
+        assert!(!default.includes_file(root, &root.join("a.txt")));
+    }
+}
@@ -1,24 +1,40 @@
 pub mod keeper;
+mod parse;
+
+use std::sync::Arc;
 
 use anyhow::Result;
 use camino::{Utf8Path as Path, Utf8PathBuf};
 use chrono::{DateTime, Utc};
 use serde::Serialize;
 
+use crate::excerpt::Excerpt;
+use crate::markup;
+use crate::outline::{self, Heading};
+
 pub use keeper::Keeper;
 
+/// Only built through [`FrontmatterFile::new`], so that what's derived from the frontmatter and
+/// body matches them
 #[derive(Debug, Clone, Serialize, PartialEq, Eq)]
 pub struct FrontmatterFile {
-    pub name: String,
-    pub frontmatter: Option<serde_yaml::Mapping>,
-    pub body: String,
-    pub modified: DateTime<Utc>,
-    pub created: DateTime<Utc>,
+    name: String,
+    frontmatter: Option<serde_yaml::Mapping>,
+    body: String,
+    modified: DateTime<Utc>,
+    created: DateTime<Utc>,
+    outline: Vec<Heading>,
+    /// `frontmatter` as JSON, which is what queries and sorting look at. Shared so that
+    /// summarising a clone of the file doesn't copy it.
+    #[serde(skip)]
+    json_frontmatter: Option<Arc<JsonFrontmatter>>,
 }
 
+pub type JsonFrontmatter = serde_json::Map<String, serde_json::Value>;
+
 impl PartialOrd for FrontmatterFile {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.created.cmp(&other.created))
+        Some(self.cmp(other))
     }
 }
 
@@ -28,18 +44,22 @@ impl Ord for FrontmatterFile {
     }
 }
 
-#[derive(Debug, Serialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
 pub struct Short {
     pub name: String,
     pub frontmatter: Option<serde_yaml::Mapping>,
     one_liner: Option<String>,
+    pub excerpt: Option<String>,
+    pub outline: Option<Vec<Heading>>,
     pub modified: DateTime<Utc>,
     pub created: DateTime<Utc>,
+    #[serde(skip)]
+    json_frontmatter: Option<Arc<JsonFrontmatter>>,
 }
 
 impl PartialOrd for Short {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.created.cmp(&other.created))
+        Some(self.cmp(other))
     }
 }
 
@@ -50,14 +70,27 @@ impl Ord for Short {
 }
 
 impl From<FrontmatterFile> for Short {
-    fn from(
+    fn from(file: FrontmatterFile) -> Self {
+        Self::new(file, None, false)
+    }
+}
+
+impl Short {
+    /// Summarises `file`, cutting an excerpt from its body and including its outline if they
+    /// are asked for
+    #[must_use]
+    pub fn new(
         FrontmatterFile {
             name,
             frontmatter,
             body,
             modified,
             created,
+            outline,
+            json_frontmatter,
         }: FrontmatterFile,
+        excerpt: Option<Excerpt>,
+        include_outline: bool,
     ) -> Self {
         let lines = body.lines().collect::<Vec<_>>();
         let one_liner = if lines.len() == 1 {
@@ -65,12 +98,102 @@ impl From<FrontmatterFile> for Short {
         } else {
             None
         };
+        let excerpt = excerpt.and_then(|excerpt| excerpt.cut(&body));
         Self {
             name,
             frontmatter,
             one_liner,
+            excerpt,
+            outline: include_outline.then_some(outline),
+            modified,
+            created,
+            json_frontmatter,
+        }
+    }
+
+    pub(crate) fn json_frontmatter(&self) -> Option<&JsonFrontmatter> {
+        self.json_frontmatter.as_deref()
+    }
+
+    /// Keeps only `fields`. `name`, `created`, `modified`, `one_liner`, `excerpt` and `outline`
+    /// pick out those attributes of the file; anything else is a frontmatter key, which may be
+    /// nested.
+    #[must_use]
+    pub fn project(self, fields: &[&str]) -> Projected {
+        let frontmatter_keys = fields
+            .iter()
+            .filter(|field| {
+                !matches!(
+                    **field,
+                    "name" | "created" | "modified" | "one_liner" | "excerpt" | "outline"
+                )
+            })
+            .collect::<Vec<_>>();
+        let frontmatter = self.frontmatter.and_then(|frontmatter| {
+            let projected = frontmatter_keys
+                .iter()
+                .filter_map(|key| {
+                    let value = crate::key_path::get_yaml(&frontmatter, key)?;
+                    Some(((**key).into(), value.clone()))
+                })
+                .collect::<serde_yaml::Mapping>();
+            (!projected.is_empty()).then_some(projected)
+        });
+
+        Projected {
+            name: fields.contains(&"name").then_some(self.name),
+            frontmatter: frontmatter.map(Some),
+            one_liner: fields.contains(&"one_liner").then_some(self.one_liner),
+            excerpt: fields.contains(&"excerpt").then_some(self.excerpt),
+            outline: fields.contains(&"outline").then_some(self.outline),
+            modified: fields.contains(&"modified").then_some(self.modified),
+            created: fields.contains(&"created").then_some(self.created),
+        }
+    }
+}
+
+/// A [`Short`] with only some of its fields. Fields left out are `None` and aren't serialized,
+/// while fields that were kept but have no value are `Some(None)` and serialized as null, as they
+/// are for a [`Short`]. With every field kept it serializes exactly like a [`Short`].
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct Projected {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frontmatter: Option<Option<serde_yaml::Mapping>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    one_liner: Option<Option<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub excerpt: Option<Option<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub outline: Option<Option<Vec<Heading>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub modified: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created: Option<DateTime<Utc>>,
+}
+
+impl From<Short> for Projected {
+    fn from(
+        Short {
+            name,
+            frontmatter,
+            one_liner,
+            excerpt,
+            outline,
             modified,
             created,
+            json_frontmatter: _,
+        }: Short,
+    ) -> Self {
+        Self {
+            name: Some(name),
+            frontmatter: Some(frontmatter),
+            one_liner: Some(one_liner),
+            excerpt: Some(excerpt),
+            outline: Some(outline),
+            modified: Some(modified),
+            created: Some(created),
         }
     }
 }
@@ -79,13 +202,40 @@ impl From<FrontmatterFile> for Short {
 pub enum ReadFromPathError {
     #[error("Failed to parse frontmatter for '{0}': {1}")]
     Yaml(String, serde_yaml::Error),
+    #[error("Failed to parse TOML frontmatter for '{0}': {1}")]
+    Toml(String, toml::de::Error),
     #[error("Failed to load: {0}")]
     Io(#[from] std::io::Error),
-    #[error("Tried to read from path with no file name: {0}")]
-    NoFileNamePath(Utf8PathBuf),
+    #[error("Tried to read from path outside of the root directory: {0}")]
+    NotInRoot(Utf8PathBuf),
 }
 
 impl FrontmatterFile {
+    /// Outlines `body` and converts `frontmatter` to JSON, so that each only needs to be done
+    /// once
+    #[must_use]
+    pub fn new(
+        name: String,
+        frontmatter: Option<serde_yaml::Mapping>,
+        body: String,
+        modified: DateTime<Utc>,
+        created: DateTime<Utc>,
+    ) -> Self {
+        let outline = outline::outline(&body);
+        let json_frontmatter = frontmatter
+            .as_ref()
+            .map(|frontmatter| Arc::new(markup::yaml_to_json(frontmatter)));
+        Self {
+            name,
+            frontmatter,
+            body,
+            modified,
+            created,
+            outline,
+            json_frontmatter,
+        }
+    }
+
     #[must_use]
     pub fn name(&self) -> &str {
         &self.name
@@ -96,6 +246,13 @@ impl FrontmatterFile {
         self.frontmatter.as_ref()
     }
 
+    /// The frontmatter as JSON, as queries and sorting see it, converted once when the file was
+    /// loaded
+    #[must_use]
+    pub fn json_frontmatter(&self) -> Option<&JsonFrontmatter> {
+        self.json_frontmatter.as_deref()
+    }
+
     #[must_use]
     pub fn body(&self) -> &str {
         &self.body
@@ -111,47 +268,39 @@ impl FrontmatterFile {
         &self.modified
     }
 
-    pub fn read_from_path(path: &Path) -> Result<Self, ReadFromPathError> {
-        let name = path
-            .file_name()
-            .ok_or_else(|| ReadFromPathError::NoFileNamePath(path.to_path_buf()))?
-            .to_owned();
+    #[must_use]
+    pub fn outline(&self) -> &[Heading] {
+        &self.outline
+    }
+
+    /// Loads the file at `path`, naming it by its path relative to `root`.
+    pub fn read_from_path(root: &Path, path: &Path) -> Result<Self, ReadFromPathError> {
+        match Self::read_from_path_lenient(root, path)? {
+            (file, None) => Ok(file),
+            (_, Some(err)) => Err(err),
+        }
+    }
+
+    /// Like [`FrontmatterFile::read_from_path`], but a file whose frontmatter doesn't parse is
+    /// still loaded, without frontmatter, alongside the error. Only unreadable files fail.
+    pub fn read_from_path_lenient(
+        root: &Path,
+        path: &Path,
+    ) -> Result<(Self, Option<ReadFromPathError>), ReadFromPathError> {
+        let name = crate::fs::relative_name(root, path)
+            .ok_or_else(|| ReadFromPathError::NotInRoot(path.to_path_buf()))?;
         let metadata = std::fs::metadata(path)?;
         let modified = metadata.modified()?.into();
         let created = metadata.created()?.into();
         let string = std::fs::read_to_string(path)?;
 
-        if !string.starts_with("---\n") {
-            let md = FrontmatterFile {
-                name,
-                frontmatter: None,
-                body: string,
-                modified,
-                created,
-            };
-            return Ok(md);
-        }
-
-        let [_, frontmatter, body] = string.splitn(3, "---\n").collect::<Vec<_>>()[..] else {
-            let md = FrontmatterFile {
-                name,
-                frontmatter: None,
-                body: string,
-                modified,
-                created,
-            };
-            return Ok(md);
+        let (frontmatter, body) = parse::split(&name, &string);
+        let (frontmatter, err) = match frontmatter {
+            Ok(frontmatter) => (frontmatter, None),
+            Err(err) => (None, Some(err)),
         };
 
-        let frontmatter = serde_yaml::from_str(frontmatter)
-            .map_err(|err| ReadFromPathError::Yaml(name.clone(), err))?;
-
-        Ok(FrontmatterFile {
-            name,
-            frontmatter: Some(frontmatter),
-            body: body.to_owned(),
-            modified,
-            created,
-        })
+        let file = FrontmatterFile::new(name, frontmatter, body.to_owned(), modified, created);
+        Ok((file, err))
     }
 }
@@ -1,21 +1,36 @@
 use std::{
-    collections::{hash_map::Values, HashMap},
-    sync::{Arc, LockResult, Mutex, MutexGuard},
+    collections::{
+        hash_map::{Iter, Values},
+        BTreeMap, HashMap,
+    },
+    sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard},
+    time::Instant,
 };
 
 use camino::{Utf8Path, Utf8PathBuf};
+use serde::Serialize;
+use tracing::{debug, error, warn};
 
-use crate::fs::{self, path_has_extensions};
+use crate::{
+    changes::{self, Change, RENAME_WAIT},
+    filter::Filter,
+    frontmatter_index, fs, links, search,
+};
 
-use super::FrontmatterFile;
+use super::{FrontmatterFile, ReadFromPathError};
 
 // Let's keep the possible events simpler for our needs
 #[derive(Debug, PartialEq)]
 enum FsEvent {
     Rename,
+    RenameFrom,
+    RenameTo,
+    RenameBoth,
     Edit,
     Create,
+    CreateFolder,
     Delete,
+    DeleteFolder,
     Ignored,
     Unhandled(notify::EventKind),
 }
@@ -28,19 +43,67 @@ impl From<notify::EventKind> for FsEvent {
         };
         match event_kind {
             EventKind::Modify(ModifyKind::Name(RenameMode::Any)) => Self::Rename,
+            EventKind::Modify(ModifyKind::Name(RenameMode::From)) => Self::RenameFrom,
+            EventKind::Modify(ModifyKind::Name(RenameMode::To)) => Self::RenameTo,
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => Self::RenameBoth,
             EventKind::Modify(ModifyKind::Data(DataChange::Content | DataChange::Any)) => {
                 Self::Edit
             }
             EventKind::Remove(RemoveKind::File) => Self::Delete,
+            EventKind::Remove(RemoveKind::Folder) => Self::DeleteFolder,
             EventKind::Create(CreateKind::File) => Self::Create,
+            EventKind::Create(CreateKind::Folder) => Self::CreateFolder,
             EventKind::Access(AccessKind::Close(AccessMode::Write)) => Self::Ignored,
             unhandled => Self::Unhandled(unhandled),
         }
     }
 }
 
+impl FsEvent {
+    /// Whether the event may concern a directory, rather than only a single file
+    fn may_be_folder(&self) -> bool {
+        matches!(
+            self,
+            Self::Rename
+                | Self::RenameFrom
+                | Self::RenameTo
+                | Self::RenameBoth
+                | Self::CreateFolder
+                | Self::DeleteFolder
+        )
+    }
+}
+
 pub struct Keeper {
-    pub inner: HashMap<Utf8PathBuf, FrontmatterFile>,
+    pub root: Utf8PathBuf,
+    inner: HashMap<Utf8PathBuf, FrontmatterFile>,
+    search_index: search::Index,
+    frontmatter_index: frontmatter_index::Index,
+    link_graph: links::Graph,
+    options: Options,
+    errors: BTreeMap<Utf8PathBuf, LoadError>,
+    changes: changes::Recorder,
+    /// Whether a thread is waiting to release renames that [`changes::Recorder`] holds back
+    is_releasing_renames: bool,
+    #[cfg(feature = "html")]
+    rendered: crate::render::Cache,
+}
+
+/// How a [`Keeper`] finds and reads files, both when it's created and as they change
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// Keep files whose frontmatter doesn't parse, rather than refusing to load them
+    pub lenient: bool,
+    pub filter: Filter,
+    /// Frontmatter keys to index for faster queries. Every top-level key is indexed by default.
+    pub index_keys: Option<Vec<String>>,
+}
+
+/// A file that couldn't be loaded, or was loaded without its frontmatter
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct LoadError {
+    pub name: String,
+    pub message: String,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -51,154 +114,430 @@ pub enum NewKeeperError {
     ReadFrontmatterFromPath(#[from] super::ReadFromPathError),
 }
 
+/// What reading a file gave: the file, if it can be served, and what went wrong, if anything
+type Read = (Option<FrontmatterFile>, Option<ReadFromPathError>);
+
+fn read_file(root: &Utf8Path, path: &Utf8Path, lenient: bool) -> Read {
+    let result = if lenient {
+        FrontmatterFile::read_from_path_lenient(root, path)
+    } else {
+        FrontmatterFile::read_from_path(root, path).map(|file| (file, None))
+    };
+    match result {
+        Ok((file, err)) => (Some(file), err),
+        Err(err) => (None, Some(err)),
+    }
+}
+
+fn load_dir(
+    root: &Utf8Path,
+    dir: &Utf8Path,
+    options: &Options,
+) -> Result<Vec<(Utf8PathBuf, Read)>, std::io::Error> {
+    let files = fs::filepaths(root, dir, &options.filter)?
+        .into_iter()
+        .map(|path| {
+            let read = read_file(root, &path, options.lenient);
+            (path, read)
+        })
+        .collect();
+    Ok(files)
+}
+
 impl Keeper {
+    /// Loads every markdown file under `path`, descending into subdirectories. Fails if any file
+    /// can't be loaded.
     pub fn new(path: &Utf8Path) -> Result<Self, NewKeeperError> {
-        let markdown_fps = fs::filepaths_with_extensions(path, &["md"])?
-            .into_iter()
-            .map(|path| -> Result<_, super::ReadFromPathError> {
-                let md = FrontmatterFile::read_from_path(&path)?;
+        Self::with_options(path, Options::default())
+    }
 
-                Ok((path, md))
-            })
-            .collect::<Result<HashMap<_, _>, _>>()?;
-        Ok(Keeper {
-            inner: markdown_fps,
-        })
+    /// Like [`Keeper::new`], but files whose frontmatter doesn't parse are served without it, and
+    /// files that can't be read are left out. Either way they're listed in [`Keeper::errors`].
+    pub fn new_lenient(path: &Utf8Path) -> Result<Self, NewKeeperError> {
+        Self::with_options(
+            path,
+            Options {
+                lenient: true,
+                ..Options::default()
+            },
+        )
+    }
+
+    /// Loads the files under `path` that `options.filter` includes, leniently or not
+    pub fn with_options(path: &Utf8Path, options: Options) -> Result<Self, NewKeeperError> {
+        let mut keeper = Keeper::from_files(path.to_owned(), HashMap::new());
+        keeper.frontmatter_index = frontmatter_index::Index::new(options.index_keys.clone());
+        keeper.link_graph = links::Graph::new(options.filter.extensions());
+        for (file_path, (file, err)) in load_dir(path, path, &options)? {
+            match err {
+                Some(err) if !options.lenient => return Err(err.into()),
+                err => keeper.store(file_path, (file, err)),
+            }
+        }
+        keeper.options = options;
+        Ok(keeper)
+    }
+
+    #[must_use]
+    pub fn from_files(root: Utf8PathBuf, files: HashMap<Utf8PathBuf, FrontmatterFile>) -> Self {
+        let mut keeper = Keeper {
+            root,
+            inner: HashMap::new(),
+            search_index: search::Index::default(),
+            frontmatter_index: frontmatter_index::Index::default(),
+            link_graph: links::Graph::default(),
+            options: Options::default(),
+            errors: BTreeMap::new(),
+            changes: changes::Recorder::default(),
+            is_releasing_renames: false,
+            #[cfg(feature = "html")]
+            rendered: crate::render::Cache::default(),
+        };
+        for (path, file) in files {
+            keeper.insert(path, file);
+        }
+        keeper
     }
 
     #[must_use]
     pub fn files(&self) -> Values<'_, Utf8PathBuf, FrontmatterFile> {
         self.inner.values()
     }
+
+    /// Every file, by path
+    #[must_use]
+    pub fn iter(&self) -> Iter<'_, Utf8PathBuf, FrontmatterFile> {
+        self.inner.iter()
+    }
+
+    #[must_use]
+    pub fn get(&self, path: &Utf8Path) -> Option<&FrontmatterFile> {
+        self.inner.get(path)
+    }
+
+    /// Files that couldn't be loaded, ordered by path
+    pub fn errors(&self) -> impl Iterator<Item = &LoadError> {
+        self.errors.values()
+    }
+
+    /// Changes to the files from now on, as the watcher makes them
+    pub fn subscribe(&self) -> tokio::sync::broadcast::Receiver<Change> {
+        self.changes.subscribe()
+    }
+
+    pub(crate) fn search_index(&self) -> &search::Index {
+        &self.search_index
+    }
+
+    pub(crate) fn frontmatter_index(&self) -> &frontmatter_index::Index {
+        &self.frontmatter_index
+    }
+
+    pub(crate) fn link_graph(&self) -> &links::Graph {
+        &self.link_graph
+    }
+
+    /// Every change to the files goes through here or [`Keeper::remove`] so that indexes and
+    /// subscribers stay current
+    pub(crate) fn insert(&mut self, path: Utf8PathBuf, file: FrontmatterFile) {
+        self.search_index.insert(&path, file.body());
+        self.frontmatter_index
+            .insert(&path, file.json_frontmatter());
+        self.link_graph.insert(file.name(), file.body());
+        #[cfg(feature = "html")]
+        self.rendered.invalidate(file.name());
+        self.changes
+            .record(&path, self.inner.get(&path), Some(&file));
+        self.inner.insert(path, file);
+    }
+
+    pub(crate) fn remove(&mut self, path: &Utf8Path) -> Option<FrontmatterFile> {
+        self.errors.remove(path);
+        self.search_index.remove(path);
+        self.frontmatter_index.remove(path);
+        let file = self.inner.remove(path)?;
+        self.changes.record(path, Some(&file), None);
+        self.link_graph.remove(file.name());
+        #[cfg(feature = "html")]
+        self.rendered.invalidate(file.name());
+        Some(file)
+    }
+
+    /// The body of `file` as HTML, rendered on first request and cached until the file changes
+    #[cfg(feature = "html")]
+    pub(crate) fn html(&self, file: &FrontmatterFile) -> String {
+        self.rendered.get_or_render(file.name(), file.body())
+    }
 }
 
 impl Keeper {
-    fn process_rename_event(&mut self, path: &Utf8Path) {
-        let was_removed = self.inner.remove(path).is_some();
-        if !was_removed {
-            let file = match FrontmatterFile::read_from_path(path) {
-                Ok(file) => file,
-                Err(err) => {
-                    eprintln!("Couldn't load file ({path:?}) after Create event: {err}");
-                    return;
-                }
-            };
-            self.inner.insert(path.to_owned(), file);
+    /// Keeps whatever could be read from `path`, and tracks whether it failed to load. A file
+    /// that fails to reload keeps its previous version, unless loading is lenient.
+    fn store(&mut self, path: Utf8PathBuf, (file, err): Read) {
+        match err {
+            Some(err) => {
+                warn!("Couldn't load file ({path:?}): {err}");
+                let name = fs::relative_name(&self.root, &path).unwrap_or_else(|| path.to_string());
+                let message = err.to_string();
+                self.errors
+                    .insert(path.clone(), LoadError { name, message });
+            }
+            None => {
+                self.errors.remove(&path);
+            }
+        }
+        if let Some(file) = file {
+            self.insert(path, file);
         }
     }
 
-    fn process_edit_event(&mut self, path: &Utf8Path) {
-        let Some(file) = self.inner.get_mut(path) else {
-            eprintln!("Couldn't find ({path:?}) in Edit event.");
+    fn reload_file(&mut self, path: &Utf8Path) {
+        let read = read_file(&self.root, path, self.options.lenient);
+        self.store(path.to_owned(), read);
+    }
+
+    /// Whether `path` is a file that would be served, going by its name alone
+    fn includes_file(&self, path: &Utf8Path) -> bool {
+        self.options.filter.includes_file(&self.root, path)
+    }
+
+    fn insert_folder(&mut self, path: &Utf8Path) {
+        if !self.options.filter.includes_dir(&self.root, path) {
             return;
-        };
-        let new_file = match FrontmatterFile::read_from_path(path) {
-            Ok(new_file) => new_file,
+        }
+        let files = match load_dir(&self.root, path, &self.options) {
+            Ok(files) => files,
             Err(err) => {
-                eprintln!("Couldn't load file ({path:?}) after Edit event: {err}");
+                warn!("Couldn't load directory ({path:?}): {err}");
                 return;
             }
         };
-        *file = new_file;
+        for (path, read) in files {
+            self.store(path, read);
+        }
+    }
+
+    fn insert_file(&mut self, path: &Utf8Path) {
+        if !self.includes_file(path) {
+            return;
+        }
+        self.reload_file(path);
+    }
+
+    /// Removes the file at `path`, or every file beneath it if it was a directory
+    fn remove_path(&mut self, path: &Utf8Path) -> bool {
+        let removed = self
+            .inner
+            .keys()
+            .filter(|file_path| file_path.starts_with(path))
+            .cloned()
+            .collect::<Vec<_>>();
+        for file_path in &removed {
+            self.remove(file_path);
+        }
+        let error_count = self.errors.len();
+        self.errors
+            .retain(|file_path, _| !file_path.starts_with(path));
+        !removed.is_empty() || self.errors.len() < error_count
+    }
+
+    fn process_rename_event(&mut self, path: &Utf8Path) {
+        if path.exists() {
+            self.process_rename_to_event(path);
+        } else {
+            self.process_rename_from_event(path);
+        }
+    }
+
+    fn process_rename_from_event(&mut self, path: &Utf8Path) {
+        let was_removed = self.remove_path(path);
+        if !was_removed && self.includes_file(path) {
+            warn!("Couldn't find ({path:?}) in Rename event.");
+        }
+    }
+
+    fn process_rename_to_event(&mut self, path: &Utf8Path) {
+        if path.is_dir() {
+            self.insert_folder(path);
+        } else {
+            self.insert_file(path);
+        }
+    }
+
+    fn process_edit_event(&mut self, path: &Utf8Path) {
+        if !self.inner.contains_key(path) && !self.errors.contains_key(path) {
+            warn!("Couldn't find ({path:?}) in Edit event.");
+            return;
+        }
+        self.reload_file(path);
     }
 
     fn process_removal_event(&mut self, path: &Utf8Path) {
-        let was_removed = self.inner.remove(path).is_some();
-        if !was_removed {
-            eprintln!("Couldn't find ({path:?}) in Remove event..");
+        let had_error = self.errors.contains_key(path);
+        let was_removed = self.remove(path).is_some();
+        if !was_removed && !had_error {
+            warn!("Couldn't find ({path:?}) in Remove event..");
         }
     }
 
+    fn process_folder_removal_event(&mut self, path: &Utf8Path) {
+        self.remove_path(path);
+    }
+
     fn process_create_event(&mut self, path: &Utf8Path) {
         if self.inner.contains_key(path) {
-            eprintln!(
-                "A Create event occurred for a path ({path:?}) but it already exists in memory."
-            );
+            warn!("A Create event occurred for a path ({path:?}) but it already exists in memory.");
             return;
         }
-        let new_file = match FrontmatterFile::read_from_path(path) {
-            Ok(new_file) => new_file,
-            Err(err) => {
-                eprintln!("Couldn't load file ({path:?}) during Create event: {err}");
-                return;
-            }
-        };
-        self.inner.insert(path.to_owned(), new_file);
+        self.reload_file(path);
+    }
+
+    fn process_folder_create_event(&mut self, path: &Utf8Path) {
+        // Files may have been written into the directory before it was being watched
+        self.insert_folder(path);
     }
 }
 
+/// A [`Keeper`] shared between requests, which read it concurrently, and the watcher, which
+/// updates it. A panic while it's held doesn't stop it being served, but a panic partway through
+/// an update may leave the indexes out of step with the file until it next changes.
 #[derive(Clone)]
-pub struct ArcMutex(pub Arc<Mutex<Keeper>>);
+pub struct ArcRwLock(pub Arc<RwLock<Keeper>>);
 
-impl ArcMutex {
+impl ArcRwLock {
     #[must_use]
     pub fn new(keeper: Keeper) -> Self {
-        Self(Arc::new(Mutex::new(keeper)))
+        Self(Arc::new(RwLock::new(keeper)))
+    }
+
+    pub fn read(&self) -> RwLockReadGuard<'_, Keeper> {
+        self.0.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn write(&self) -> RwLockWriteGuard<'_, Keeper> {
+        self.0.write().unwrap_or_else(PoisonError::into_inner)
     }
 
-    pub fn lock(&self) -> LockResult<MutexGuard<'_, Keeper>> {
-        self.0.as_ref().lock()
+    /// Reports files moved out of the watched directory as deleted, once it's clear that their
+    /// rename won't be completed. Only one rename is held back at a time, so one thread waits for
+    /// whichever is held, until there are none left.
+    fn release_renames_later(&self, keeper: &mut Keeper) {
+        if keeper.is_releasing_renames {
+            return;
+        }
+        keeper.is_releasing_renames = true;
+        let shared = self.clone();
+        std::thread::spawn(move || {
+            let mut wait = RENAME_WAIT;
+            loop {
+                std::thread::sleep(wait);
+                let mut keeper = shared.write();
+                let now = Instant::now();
+                match keeper.changes.release_expired(now) {
+                    Some(deadline) => wait = deadline - now,
+                    None => {
+                        keeper.changes.publish();
+                        keeper.is_releasing_renames = false;
+                        return;
+                    }
+                }
+            }
+        });
     }
 }
 
-impl notify::EventHandler for ArcMutex {
+fn utf8_path(path: &std::path::Path) -> Option<Utf8PathBuf> {
+    match Utf8PathBuf::try_from(path.to_path_buf()) {
+        Ok(path) => Some(path),
+        Err(err) => {
+            error!(
+                "Event filepath ({}) was not UTF-8: {err}\n\nNon-UTF-8 paths not supported.",
+                path.display()
+            );
+            None
+        }
+    }
+}
+
+impl notify::EventHandler for ArcRwLock {
     fn handle_event(&mut self, event: notify::Result<notify::Event>) {
         match event {
-            Ok(notify::Event {
-                kind,
-                paths,
-                attrs: _,
-            }) => {
+            Ok(notify::Event { kind, paths, attrs }) => {
                 let path = paths.first().expect("event must have at least one path");
-                let path = match Utf8PathBuf::try_from(path.clone()) {
-                    Ok(path) => path,
-                    Err(err) => {
-                        eprintln!("Event filepath ({}) was not UTF-8: {err}\n\nNon-UTF-8 paths not supported.", path.display());
-                        return;
-                    }
+                let Some(path) = utf8_path(path) else {
+                    return;
                 };
-                if !path_has_extensions(&path, &["md"]) {
+                let event = FsEvent::from(kind);
+                // Checked before waiting on readers, as most events are for files not served
+                if !event.may_be_folder() && !self.read().includes_file(&path) {
                     return;
                 }
-                let mut map = match self.lock() {
-                    Ok(map) => map,
-                    Err(err) => {
-                        eprintln!("Failed to lock data map during notify event: {err}");
-                        return;
-                    }
-                };
-                match FsEvent::from(kind) {
+                let tracker = attrs.tracker();
+                let mut map = self.write();
+                if event != FsEvent::RenameTo {
+                    map.changes.release();
+                }
+                match event {
                     FsEvent::Rename => {
                         map.process_rename_event(&path);
                     }
+                    FsEvent::RenameFrom => {
+                        map.process_rename_from_event(&path);
+                        if let Some(tracker) = tracker {
+                            map.changes.hold(Some(tracker), &path);
+                            if map.changes.is_subscribed() {
+                                self.release_renames_later(&mut map);
+                            }
+                        }
+                    }
+                    FsEvent::RenameTo => {
+                        map.process_rename_to_event(&path);
+                        map.changes.pair(tracker, &path);
+                    }
+                    FsEvent::RenameBoth => {
+                        map.process_rename_from_event(&path);
+                        if let Some(to) = paths.get(1).and_then(|to| utf8_path(to)) {
+                            map.changes.hold(tracker, &path);
+                            map.process_rename_to_event(&to);
+                            map.changes.pair(tracker, &to);
+                        }
+                    }
                     FsEvent::Edit => {
                         map.process_edit_event(&path);
                     }
                     FsEvent::Delete => {
                         map.process_removal_event(&path);
                     }
+                    FsEvent::DeleteFolder => {
+                        map.process_folder_removal_event(&path);
+                    }
                     FsEvent::Create => {
                         map.process_create_event(&path);
                     }
+                    FsEvent::CreateFolder => {
+                        map.process_folder_create_event(&path);
+                    }
                     FsEvent::Ignored => (),
-                    FsEvent::Unhandled(event) => println!("unhandled watch event: {event:?}"),
+                    FsEvent::Unhandled(event) => debug!("unhandled watch event: {event:?}"),
                 }
+                map.changes.publish();
             }
-            Err(e) => println!("watch error: {e:?}"),
+            Err(e) => error!("watch error: {e:?}"),
         }
     }
 }
 
 #[cfg(test)]
 mod test {
-    use std::io::Write;
+    use std::{collections::HashMap, io::Write};
 
     use camino::Utf8PathBuf;
     use notify::{EventHandler, RecursiveMode, Watcher};
 
     use crate::frontmatter_file::keeper::FsEvent;
 
-    use super::{ArcMutex, Keeper};
+    use super::{ArcRwLock, Keeper, Options};
+    use crate::filter::Filter;
 
     struct TestFile {
         path: Utf8PathBuf,
@@ -231,16 +570,217 @@ mod test {
         }
     }
 
+    struct TestDir {
+        path: Utf8PathBuf,
+    }
+
+    impl TestDir {
+        fn create(path: Utf8PathBuf) -> std::io::Result<Self> {
+            if path.exists() {
+                std::fs::remove_dir_all(&path)?;
+            }
+            std::fs::create_dir_all(&path)?;
+            Ok(Self { path })
+        }
+
+        fn write(&self, name: &str, contents: &str) -> std::io::Result<()> {
+            let path = self.path.join(name);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(path, contents)
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            if self.path.exists() {
+                std::fs::remove_dir_all(&self.path).unwrap();
+            }
+        }
+    }
+
+    fn sorted_names(keeper: &Keeper) -> Vec<&str> {
+        let mut names = keeper.files().map(|file| file.name()).collect::<Vec<_>>();
+        names.sort_unstable();
+        names
+    }
+
+    #[test]
+    fn nested_files() {
+        let wd = Utf8PathBuf::try_from(std::env::temp_dir().join("custard_nested_files")).unwrap();
+        let test_dir = TestDir::create(wd.clone()).unwrap();
+        test_dir
+            .write("posts/2024/hello.md", "---\ntitle: Hello\n---\nHi!\n")
+            .unwrap();
+        test_dir.write("posts/notes.txt", "Not markdown").unwrap();
+        test_dir.write("top.md", "Top level").unwrap();
+
+        let mut keeper = Keeper::new(&wd).unwrap();
+        pretty_assertions::assert_eq!(vec!["posts/2024/hello.md", "top.md"], sorted_names(&keeper));
+
+        test_dir.write("notes/idea.md", "An idea").unwrap();
+        keeper.process_folder_create_event(&wd.join("notes"));
+        pretty_assertions::assert_eq!(
+            vec!["notes/idea.md", "posts/2024/hello.md", "top.md"],
+            sorted_names(&keeper)
+        );
+
+        keeper.process_rename_from_event(&wd.join("posts"));
+        pretty_assertions::assert_eq!(vec!["notes/idea.md", "top.md"], sorted_names(&keeper));
+    }
+
+    #[test]
+    fn filtered_files() {
+        let wd =
+            Utf8PathBuf::try_from(std::env::temp_dir().join("custard_filtered_files")).unwrap();
+        let test_dir = TestDir::create(wd.clone()).unwrap();
+        test_dir.write("a.md", "A").unwrap();
+        test_dir.write("b.markdown", "B").unwrap();
+        test_dir.write("c.txt", "C").unwrap();
+        test_dir.write("_drafts/d.md", "D").unwrap();
+        test_dir.write(".obsidian/e.md", "E").unwrap();
+
+        let options = Options {
+            lenient: false,
+            filter: Filter::new(["md", "markdown"], ["_drafts/", ".*"]).unwrap(),
+            index_keys: None,
+        };
+        let mut keeper = Keeper::with_options(&wd, options).unwrap();
+        pretty_assertions::assert_eq!(vec!["a.md", "b.markdown"], sorted_names(&keeper));
+
+        test_dir.write("_drafts/f.md", "F").unwrap();
+        keeper.process_rename_to_event(&wd.join("_drafts/f.md"));
+        test_dir.write(".g.md", "G").unwrap();
+        keeper.process_rename_to_event(&wd.join(".g.md"));
+        test_dir.write("_drafts/nested/h.md", "H").unwrap();
+        keeper.process_folder_create_event(&wd.join("_drafts/nested"));
+        pretty_assertions::assert_eq!(
+            vec!["a.md", "b.markdown"],
+            sorted_names(&keeper),
+            "watch events respect the filter too"
+        );
+
+        test_dir.write("posts/i.markdown", "I").unwrap();
+        keeper.process_folder_create_event(&wd.join("posts"));
+        pretty_assertions::assert_eq!(
+            vec!["a.md", "b.markdown", "posts/i.markdown"],
+            sorted_names(&keeper)
+        );
+    }
+
+    #[test]
+    fn lenient_loading() {
+        let wd =
+            Utf8PathBuf::try_from(std::env::temp_dir().join("custard_lenient_loading")).unwrap();
+        let test_dir = TestDir::create(wd.clone()).unwrap();
+        test_dir
+            .write("good.md", "---\ntitle: Good\n---\nFine\n")
+            .unwrap();
+        test_dir
+            .write("bad.md", "---\ntitle: [unclosed\n---\nStill readable\n")
+            .unwrap();
+
+        assert!(
+            Keeper::new(&wd).is_err(),
+            "strict loading refuses bad files"
+        );
+
+        let mut keeper = Keeper::new_lenient(&wd).unwrap();
+        pretty_assertions::assert_eq!(vec!["bad.md", "good.md"], sorted_names(&keeper));
+        let bad = keeper.inner.get(&wd.join("bad.md")).unwrap();
+        pretty_assertions::assert_eq!(None, bad.frontmatter());
+        pretty_assertions::assert_eq!("Still readable\n", bad.body());
+        let errors = keeper
+            .errors()
+            .map(|err| err.name.as_str())
+            .collect::<Vec<_>>();
+        pretty_assertions::assert_eq!(vec!["bad.md"], errors);
+
+        test_dir
+            .write("bad.md", "---\ntitle: Fixed\n---\nStill readable\n")
+            .unwrap();
+        keeper.process_edit_event(&wd.join("bad.md"));
+        pretty_assertions::assert_eq!(0, keeper.errors().count(), "fixing a file clears its error");
+    }
+
+    #[test]
+    fn survives_panics() {
+        let keeper = ArcRwLock::new(Keeper::from_files(Utf8PathBuf::from("/"), HashMap::new()));
+
+        let poisoner = keeper.clone();
+        let result = std::thread::spawn(move || {
+            let _keeper = poisoner.write();
+            panic!("while holding the lock");
+        })
+        .join();
+        assert!(result.is_err());
+
+        let first = keeper.read();
+        let second = keeper.read();
+        pretty_assertions::assert_eq!(0, first.files().count());
+        pretty_assertions::assert_eq!(0, second.files().count());
+    }
+
+    #[test]
+    fn subscribed_changes() {
+        use notify::event::{CreateKind, EventKind, ModifyKind, RenameMode};
+
+        use crate::changes::Event;
+
+        let wd =
+            Utf8PathBuf::try_from(std::env::temp_dir().join("custard_subscribed_changes")).unwrap();
+        let test_dir = TestDir::create(wd.clone()).unwrap();
+        test_dir.write("a.md", "A").unwrap();
+        let mut keeper = ArcRwLock::new(Keeper::new(&wd).unwrap());
+        let mut changes = keeper.read().subscribe();
+
+        std::fs::rename(wd.join("a.md"), wd.join("b.md")).unwrap();
+        // The order `notify` reports a rename within the watched directory in
+        for (mode, paths) in [
+            (RenameMode::From, vec![wd.join("a.md")]),
+            (RenameMode::To, vec![wd.join("b.md")]),
+            (RenameMode::Both, vec![wd.join("a.md"), wd.join("b.md")]),
+        ] {
+            let event = paths.into_iter().fold(
+                notify::Event::new(EventKind::Modify(ModifyKind::Name(mode))).set_tracker(1),
+                |event, path| event.add_path(path.into()),
+            );
+            keeper.handle_event(Ok(event));
+        }
+        test_dir.write("c.md", "C").unwrap();
+        keeper.handle_event(Ok(notify::Event::new(EventKind::Create(CreateKind::File))
+            .add_path(wd.join("c.md").into())));
+
+        let b = keeper.read().inner.get(&wd.join("b.md")).unwrap().clone();
+        let c = keeper.read().inner.get(&wd.join("c.md")).unwrap().clone();
+        let events = std::iter::from_fn(|| changes.try_recv().ok())
+            .filter_map(|change| change.into_event(None))
+            .collect::<Vec<_>>();
+        pretty_assertions::assert_eq!(
+            vec![
+                Event::Renamed {
+                    from: "a.md".to_owned(),
+                    file: b.into(),
+                },
+                Event::Created(c.into()),
+            ],
+            events
+        );
+    }
+
     #[test]
     #[allow(clippy::too_many_lines)]
     fn file_monitoring() {
         let test_file_name = "test.md";
-        let wd = Utf8PathBuf::try_from(std::env::temp_dir()).unwrap();
+        let wd =
+            Utf8PathBuf::try_from(std::env::temp_dir().join("custard_file_monitoring")).unwrap();
+        let _test_dir = TestDir::create(wd.clone()).unwrap();
         let test_file_path = wd.join(test_file_name);
         let test_file = TestFile {
             path: test_file_path,
         };
-        let keeper = ArcMutex::new(Keeper::new(&wd).unwrap());
+        let keeper = ArcRwLock::new(Keeper::new(&wd).unwrap());
 
         let (tx, rx) = std::sync::mpsc::channel();
 
@@ -262,11 +802,11 @@ mod test {
             .unwrap();
 
         watcher
-            .watch(wd.as_std_path(), RecursiveMode::NonRecursive)
+            .watch(wd.as_std_path(), RecursiveMode::Recursive)
             .unwrap();
 
         {
-            let keeper = keeper.lock().unwrap();
+            let keeper = keeper.read();
             let file = keeper.files().find(|file| file.name() == test_file_name);
             assert!(file.is_none());
         }
@@ -301,7 +841,7 @@ mod test {
         pretty_assertions::assert_eq!(FsEvent::Edit, event);
 
         {
-            let keeper = keeper.lock().unwrap();
+            let keeper = keeper.read();
             let file = keeper
                 .files()
                 .find(|file| file.name() == test_file_name)
@@ -330,7 +870,7 @@ mod test {
         pretty_assertions::assert_eq!(FsEvent::Edit, event);
 
         {
-            let keeper = keeper.lock().unwrap();
+            let keeper = keeper.read();
             let file = keeper
                 .files()
                 .find(|file| file.name() == test_file_name)
@@ -358,7 +898,7 @@ mod test {
         pretty_assertions::assert_eq!(FsEvent::Delete, event);
 
         {
-            let keeper = keeper.lock().unwrap();
+            let keeper = keeper.read();
             let file = keeper.files().find(|file| file.name() == test_file_name);
             assert!(file.is_none());
         }
@@ -0,0 +1,205 @@
+use serde_yaml::{Mapping, Value};
+
+use super::ReadFromPathError;
+
+/// TOML has a native datetime type, which becomes a string like a YAML timestamp does
+fn toml_to_yaml(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(s) => Value::String(s),
+        toml::Value::Integer(i) => Value::Number(i.into()),
+        toml::Value::Float(f) => Value::Number(f.into()),
+        toml::Value::Boolean(b) => Value::Bool(b),
+        toml::Value::Datetime(dt) => Value::String(dt.to_string()),
+        toml::Value::Array(array) => Value::Sequence(array.into_iter().map(toml_to_yaml).collect()),
+        toml::Value::Table(table) => Value::Mapping(
+            table
+                .into_iter()
+                .map(|(key, value)| (Value::String(key), toml_to_yaml(value)))
+                .collect(),
+        ),
+    }
+}
+
+fn yaml(name: &str, frontmatter: &str) -> Result<Mapping, ReadFromPathError> {
+    serde_yaml::from_str(frontmatter).map_err(|err| ReadFromPathError::Yaml(name.to_owned(), err))
+}
+
+fn toml(name: &str, frontmatter: &str) -> Result<Mapping, ReadFromPathError> {
+    let table = toml::from_str::<toml::Table>(frontmatter)
+        .map_err(|err| ReadFromPathError::Toml(name.to_owned(), err))?;
+    match toml_to_yaml(toml::Value::Table(table)) {
+        Value::Mapping(mapping) => Ok(mapping),
+        _ => unreachable!("a table converts to a mapping"),
+    }
+}
+
+/// A JSON object at the very start of the file, as Hugo allows, with the body following it.
+/// Anything else starting with `{`, like a kramdown attribute list or a Liquid tag, isn't
+/// frontmatter.
+fn json(string: &str) -> Option<(Mapping, &str)> {
+    let mut stream = serde_json::Deserializer::from_str(string)
+        .into_iter::<serde_json::Map<String, serde_json::Value>>();
+    let object = stream.next()?.ok()?;
+    let body = &string[stream.byte_offset()..];
+    let body = body
+        .strip_prefix("\r\n")
+        .or_else(|| body.strip_prefix('\n'))
+        .unwrap_or(body);
+    Some((crate::markup::yaml_to_json(object), body))
+}
+
+/// Whether `line` is `fence`, ignoring trailing whitespace and line endings
+fn is_fence(line: &str, fence: &str) -> bool {
+    line.trim_end() == fence
+}
+
+/// Splits off the text between an opening `fence` on the first line and the next line that is
+/// only `fence`. Without a closing fence there is no frontmatter.
+fn fenced<'a>(string: &'a str, fence: &str) -> Option<(&'a str, &'a str)> {
+    let mut lines = string.split_inclusive('\n');
+    let opening = lines.next()?;
+    if !is_fence(opening, fence) {
+        return None;
+    }
+
+    let start = opening.len();
+    let mut end = start;
+    for line in lines {
+        if is_fence(line, fence) {
+            return Some((&string[start..end], &string[end + line.len()..]));
+        }
+        end += line.len();
+    }
+    None
+}
+
+/// The result of parsing frontmatter, and the body that follows it
+type Split<'a> = (Result<Option<Mapping>, ReadFromPathError>, &'a str);
+
+/// Splits `string` into its frontmatter and body. Frontmatter may be YAML fenced by `---`, TOML
+/// fenced by `+++`, or a JSON object. A leading byte order mark is dropped.
+///
+/// The body is found even if the frontmatter doesn't parse, so that the file can still be served.
+pub(super) fn split<'a>(name: &str, string: &'a str) -> Split<'a> {
+    let string = string.strip_prefix('\u{feff}').unwrap_or(string);
+
+    for (fence, parse) in [("---", yaml as fn(&str, &str) -> _), ("+++", toml)] {
+        if let Some((frontmatter, body)) = fenced(string, fence) {
+            let frontmatter = if frontmatter.trim().is_empty() {
+                Ok(Mapping::new())
+            } else {
+                parse(name, frontmatter)
+            };
+            return (frontmatter.map(Some), body);
+        }
+    }
+
+    if let Some((frontmatter, body)) = json(string) {
+        return (Ok(Some(frontmatter)), body);
+    }
+
+    (Ok(None), string)
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+    use serde_yaml::Mapping;
+
+    fn mapping(yaml: &str) -> Option<Mapping> {
+        Some(serde_yaml::from_str(yaml).unwrap())
+    }
+
+    fn split(string: &str) -> (Option<Mapping>, &str) {
+        let (frontmatter, body) = super::split("a.md", string);
+        (frontmatter.unwrap(), body)
+    }
+
+    #[test]
+    fn formats() {
+        let (frontmatter, body) = split("---\ntitle: Hello\ntags: [a, b]\n---\nBody\n");
+        assert_eq!(mapping("title: Hello\ntags: [a, b]"), frontmatter);
+        assert_eq!("Body\n", body);
+
+        let (frontmatter, body) = split("+++\ntitle = \"Hello\"\ntags = [\"a\", \"b\"]\ndate = 2024-01-02T03:04:05Z\n[author]\nhandle = \"someone\"\n+++\nBody\n",
+        );
+        assert_eq!(
+            mapping("title: Hello\ntags: [a, b]\ndate: '2024-01-02T03:04:05Z'\nauthor:\n  handle: someone"),
+            frontmatter
+        );
+        assert_eq!("Body\n", body);
+
+        let (frontmatter, body) =
+            split("{\n  \"title\": \"Hello\",\n  \"tags\": [\"a\", \"b\"]\n}\nBody\n");
+        assert_eq!(mapping("title: Hello\ntags: [a, b]"), frontmatter);
+        assert_eq!("Body\n", body);
+
+        let (frontmatter, body) = split("{{< shortcode >}}\n");
+        assert_eq!(None, frontmatter);
+        assert_eq!("{{< shortcode >}}\n", body);
+
+        let (frontmatter, body) = super::split("a.md", "+++\ntitle = \n+++\nBody\n");
+        assert!(frontmatter.is_err());
+        assert_eq!("Body\n", body, "the body is still found");
+
+        for body in [
+            "{ not json\n",
+            "{: .lead}\nIntro\n",
+            "{% raw %}\n{{ page.title }}\n{% endraw %}\n",
+            "{\"a\": 1\n",
+        ] {
+            assert_eq!(
+                (None, body),
+                split(body),
+                "only a JSON object is JSON frontmatter"
+            );
+        }
+    }
+
+    #[test]
+    fn fences() {
+        let (frontmatter, body) = split("---\r\ntitle: Hello\r\n---\r\nBody\r\n");
+        assert_eq!(mapping("title: Hello"), frontmatter, "CRLF line endings");
+        assert_eq!("Body\r\n", body);
+
+        let (frontmatter, body) = split("\u{feff}---\ntitle: Hello\n---\nBody\n");
+        assert_eq!(mapping("title: Hello"), frontmatter, "byte order mark");
+        assert_eq!("Body\n", body);
+
+        let (frontmatter, body) = split("---  \ntitle: Hello\n---\t\nBody\n");
+        assert_eq!(mapping("title: Hello"), frontmatter, "trailing whitespace");
+        assert_eq!("Body\n", body);
+
+        let (frontmatter, body) = split("---\ntitle: A---\n---\nBefore\n\n---\n\n```\n---\n```\n");
+        assert_eq!(
+            mapping("title: A---"),
+            frontmatter,
+            "fences are only recognised on their own line"
+        );
+        assert_eq!("Before\n\n---\n\n```\n---\n```\n", body);
+
+        let (frontmatter, body) = split("---\nNo closing fence\n");
+        assert_eq!(None, frontmatter);
+        assert_eq!("---\nNo closing fence\n", body);
+
+        let (frontmatter, body) = split("----\nNot a fence\n----\n");
+        assert_eq!(None, frontmatter);
+        assert_eq!("----\nNot a fence\n----\n", body);
+
+        let (frontmatter, body) = split("---\n---\nBody");
+        assert_eq!(
+            Some(serde_yaml::Mapping::new()),
+            frontmatter,
+            "empty frontmatter"
+        );
+        assert_eq!("Body", body);
+
+        let (frontmatter, body) = split("---\ntitle: Hello\n---");
+        assert_eq!(
+            mapping("title: Hello"),
+            frontmatter,
+            "closing fence at end of file"
+        );
+        assert_eq!("", body);
+    }
+}
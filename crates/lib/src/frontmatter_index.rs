@@ -0,0 +1,365 @@
+use std::collections::{HashMap, HashSet};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use serde_json::Number;
+
+use crate::{
+    frontmatter_file::JsonFrontmatter,
+    frontmatter_query::{FrontmatterQuery, FrontmatterQueryMap, QueryValue, Scalar},
+    key_path,
+};
+
+/// A frontmatter scalar in a form that can be looked up. Numbers are only equal to numbers
+/// written the same way, as with [`Scalar::matches`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Term {
+    String(String),
+    Number(Number),
+    Bool(bool),
+    Null,
+}
+
+impl Term {
+    fn from_json(value: &serde_json::Value) -> Option<Self> {
+        match value {
+            serde_json::Value::String(s) => Some(Self::String(s.clone())),
+            serde_json::Value::Number(n) => Some(Self::Number(n.clone())),
+            serde_json::Value::Bool(b) => Some(Self::Bool(*b)),
+            serde_json::Value::Null => Some(Self::Null),
+            serde_json::Value::Array(_) | serde_json::Value::Object(_) => None,
+        }
+    }
+
+    fn from_scalar(scalar: &Scalar) -> Self {
+        match scalar {
+            Scalar::String(s) => Self::String(s.clone()),
+            Scalar::Number(n) => Self::Number(n.clone()),
+            Scalar::Bool(b) => Self::Bool(*b),
+            Scalar::Null => Self::Null,
+        }
+    }
+}
+
+type Postings = HashMap<String, HashMap<Term, HashSet<Utf8PathBuf>>>;
+
+/// Inverted indexes from frontmatter values to the files that have them, kept up to date by
+/// [`crate::frontmatter_file::Keeper`]. Queries use them to narrow down which files need to be
+/// checked, and every file found this way is still checked against the whole query.
+#[derive(Debug, Default)]
+pub struct Index {
+    /// The keys to index. `None` indexes every top-level key.
+    keys: Option<HashSet<String>>,
+    /// Files by the scalar value of each key
+    scalars: Postings,
+    /// Files by the elements of each key's sequence of scalars
+    elements: Postings,
+    indexed: HashMap<Utf8PathBuf, Vec<(String, Term, bool)>>,
+}
+
+impl Index {
+    pub(crate) fn new(keys: Option<Vec<String>>) -> Self {
+        Self {
+            keys: keys.map(|keys| keys.into_iter().collect()),
+            ..Self::default()
+        }
+    }
+
+    fn is_indexed(&self, key: &str) -> bool {
+        match &self.keys {
+            Some(keys) => keys.contains(key),
+            // A nested key may be satisfied by a value that isn't at the top level
+            None => !key.starts_with('/') && !key.contains('.'),
+        }
+    }
+
+    /// Indexes the JSON form of the frontmatter, as that's what queries are matched against, with
+    /// keys that aren't strings in YAML already turned into strings
+    pub(crate) fn insert(&mut self, path: &Utf8Path, frontmatter: Option<&JsonFrontmatter>) {
+        self.remove(path);
+        let Some(frontmatter) = frontmatter else {
+            return;
+        };
+
+        let values: Vec<(String, &serde_json::Value)> = match &self.keys {
+            Some(keys) => keys
+                .iter()
+                .filter_map(|key| Some((key.clone(), key_path::get_json(frontmatter, key)?)))
+                .collect(),
+            None => frontmatter
+                .iter()
+                .map(|(key, value)| (key.clone(), value))
+                .collect(),
+        };
+
+        let mut indexed = Vec::new();
+        for (key, value) in values {
+            match value {
+                serde_json::Value::Array(elements) => {
+                    for element in elements {
+                        if let Some(term) = Term::from_json(element) {
+                            indexed.push((key.clone(), term, true));
+                        }
+                    }
+                }
+                value => {
+                    if let Some(term) = Term::from_json(value) {
+                        indexed.push((key, term, false));
+                    }
+                }
+            }
+        }
+
+        for (key, term, is_element) in &indexed {
+            let postings = if *is_element {
+                &mut self.elements
+            } else {
+                &mut self.scalars
+            };
+            postings
+                .entry(key.clone())
+                .or_default()
+                .entry(term.clone())
+                .or_default()
+                .insert(path.to_owned());
+        }
+        self.indexed.insert(path.to_owned(), indexed);
+    }
+
+    pub(crate) fn remove(&mut self, path: &Utf8Path) {
+        let Some(indexed) = self.indexed.remove(path) else {
+            return;
+        };
+        for (key, term, is_element) in indexed {
+            let postings = if is_element {
+                &mut self.elements
+            } else {
+                &mut self.scalars
+            };
+            let Some(terms) = postings.get_mut(&key) else {
+                continue;
+            };
+            if let Some(paths) = terms.get_mut(&term) {
+                paths.remove(path);
+                if paths.is_empty() {
+                    terms.remove(&term);
+                }
+            }
+            if terms.is_empty() {
+                postings.remove(&key);
+            }
+        }
+    }
+
+    fn lookup<'a>(postings: &'a Postings, key: &str, scalar: &Scalar) -> HashSet<&'a Utf8PathBuf> {
+        postings
+            .get(key)
+            .and_then(|terms| terms.get(&Term::from_scalar(scalar)))
+            .map(|paths| paths.iter().collect())
+            .unwrap_or_default()
+    }
+
+    /// Files with a sequence at `key` containing every (or any) one of `scalars`
+    fn elements(&self, key: &str, scalars: &[Scalar], every: bool) -> HashSet<&Utf8PathBuf> {
+        let mut sets = scalars
+            .iter()
+            .map(|scalar| Self::lookup(&self.elements, key, scalar));
+        let first = sets.next().unwrap_or_default();
+        sets.fold(first, |acc, set| {
+            if every {
+                acc.intersection(&set).copied().collect()
+            } else {
+                acc.union(&set).copied().collect()
+            }
+        })
+    }
+
+    fn map_candidates(
+        &self,
+        map: &FrontmatterQueryMap,
+        intersect: bool,
+    ) -> Option<HashSet<&Utf8PathBuf>> {
+        map.0
+            .iter()
+            .filter(|(key, _)| self.is_indexed(key))
+            .filter_map(|(key, value)| match value {
+                QueryValue::Scalar(scalar) => Some(Self::lookup(&self.scalars, key, scalar)),
+                // Every sequence contains the empty set, and intersects it too
+                QueryValue::Vec(scalars) if scalars.is_empty() => None,
+                QueryValue::Vec(scalars) => Some(self.elements(key, scalars, !intersect)),
+                QueryValue::Comparison(_) => None,
+            })
+            .reduce(|acc, set| acc.intersection(&set).copied().collect())
+    }
+
+    /// The paths of every file that might match `query`, or `None` if the indexes can't tell
+    pub(crate) fn candidates(&self, query: &FrontmatterQuery) -> Option<HashSet<&Utf8PathBuf>> {
+        match query {
+            FrontmatterQuery::Subset(map) => self.map_candidates(map, false),
+            FrontmatterQuery::Intersect { intersect: map } => self.map_candidates(map, true),
+            FrontmatterQuery::And { and } => and
+                .iter()
+                .filter_map(|query| self.candidates(query))
+                .reduce(|acc, set| acc.intersection(&set).copied().collect()),
+            FrontmatterQuery::Or { or } => or.iter().map(|query| self.candidates(query)).try_fold(
+                HashSet::new(),
+                |mut acc, set| {
+                    acc.extend(set?);
+                    Some(acc)
+                },
+            ),
+            FrontmatterQuery::Not { .. } => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use camino::{Utf8Path, Utf8PathBuf};
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use super::Index;
+    use crate::{frontmatter_file::JsonFrontmatter, frontmatter_query::FrontmatterQuery, markup};
+
+    fn json_frontmatter(yaml: &str) -> JsonFrontmatter {
+        markup::yaml_to_json(serde_yaml::from_str::<serde_yaml::Mapping>(yaml).unwrap())
+    }
+
+    fn index(keys: Option<Vec<String>>) -> Index {
+        let mut index = Index::new(keys);
+        for (path, yaml) in [
+            ("/a.md", "tags: [essay, film]\ndraft: false\nrating: 4"),
+            (
+                "/b.md",
+                "tags: [essay]\ndraft: true\nauthor:\n  handle: tea",
+            ),
+            ("/c.md", "tags: essay\nrating: 4.0"),
+        ] {
+            index.insert(Utf8Path::new(path), Some(&json_frontmatter(yaml)));
+        }
+        index.insert(Utf8Path::new("/d.md"), None);
+        index
+    }
+
+    fn candidates(index: &Index, query: serde_json::Value) -> Option<Vec<&str>> {
+        let query = serde_json::from_value::<FrontmatterQuery>(query).unwrap();
+        index.candidates(&query).map(|paths| {
+            let mut paths = paths
+                .into_iter()
+                .map(|path| path.as_str())
+                .collect::<Vec<_>>();
+            paths.sort_unstable();
+            paths
+        })
+    }
+
+    #[test]
+    fn candidates_for() {
+        let index = index(None);
+
+        assert_eq!(
+            Some(vec!["/a.md", "/b.md"]),
+            candidates(&index, json!({ "tags": ["essay"] }))
+        );
+        assert_eq!(
+            Some(vec!["/a.md"]),
+            candidates(&index, json!({ "tags": ["essay", "film"], "draft": false }))
+        );
+        assert_eq!(
+            Some(vec!["/a.md", "/b.md"]),
+            candidates(
+                &index,
+                json!({ "$intersect": { "tags": ["film", "essay"] } })
+            )
+        );
+        assert_eq!(
+            Some(vec!["/c.md"]),
+            candidates(&index, json!({ "tags": "essay" })),
+            "scalars and sequences are kept apart"
+        );
+        assert_eq!(
+            Some(vec!["/a.md"]),
+            candidates(&index, json!({ "rating": 4 })),
+            "numbers match as they do when scanning"
+        );
+        assert_eq!(
+            Some(vec!["/a.md", "/b.md"]),
+            candidates(
+                &index,
+                json!({ "$or": [{ "draft": true }, { "tags": ["film"] }] })
+            )
+        );
+        assert_eq!(
+            Some(vec!["/b.md"]),
+            candidates(
+                &index,
+                json!({ "$and": [{ "tags": ["essay"] }, { "$not": { "draft": false } }, { "draft": true }] })
+            )
+        );
+
+        assert_eq!(None, candidates(&index, json!({ "rating": { "$gt": 3 } })));
+        assert_eq!(
+            None,
+            candidates(&index, json!({ "$not": { "draft": true } }))
+        );
+        assert_eq!(None, candidates(&index, json!({ "author.handle": "tea" })));
+        assert_eq!(
+            None,
+            candidates(
+                &index,
+                json!({ "$or": [{ "draft": true }, { "rating": { "$lt": 5 } }] })
+            )
+        );
+
+        let index = self::index(Some(vec!["author.handle".to_owned()]));
+        assert_eq!(
+            Some(vec!["/b.md"]),
+            candidates(&index, json!({ "author.handle": "tea" }))
+        );
+        assert_eq!(None, candidates(&index, json!({ "draft": true })));
+    }
+
+    #[test]
+    fn non_string_keys() {
+        let mut index = Index::new(None);
+        index.insert(
+            Utf8Path::new("/a.md"),
+            Some(&json_frontmatter("2024: x\ntrue: y")),
+        );
+
+        assert_eq!(
+            Some(vec!["/a.md"]),
+            candidates(&index, json!({ "2024": "x" })),
+            "keys are indexed as queries see them"
+        );
+        assert_eq!(
+            Some(vec!["/a.md"]),
+            candidates(&index, json!({ "true": "y" }))
+        );
+    }
+
+    #[test]
+    fn remove() {
+        let mut index = index(None);
+        index.remove(Utf8Path::new("/a.md"));
+        index.insert(
+            Utf8Path::new("/b.md"),
+            Some(&json_frontmatter("tags: [film]")),
+        );
+
+        assert_eq!(
+            Some(Vec::<&str>::new()),
+            candidates(&index, json!({ "tags": ["essay"] }))
+        );
+        assert_eq!(
+            Some(vec!["/b.md"]),
+            candidates(&index, json!({ "tags": ["film"] }))
+        );
+        assert_eq!(
+            HashSet::from([Utf8PathBuf::from("/b.md"), Utf8PathBuf::from("/c.md")]),
+            index.indexed.keys().cloned().collect()
+        );
+    }
+}
@@ -1,8 +1,11 @@
-use std::collections::HashMap;
+use std::{borrow::Cow, cmp::Ordering, collections::HashMap};
 
+use regex::{Regex, RegexBuilder};
 use serde::Deserialize;
 use serde_json::Number;
 
+use crate::{key_path, markup::parse_timestamp};
+
 #[derive(Deserialize, Debug)]
 #[cfg_attr(debug_assertions, derive(Clone))]
 #[serde(untagged)]
@@ -24,6 +27,174 @@ impl Scalar {
             _ => false,
         }
     }
+
+    /// Orders a frontmatter value relative to this scalar.
+    ///
+    /// Numbers compare numerically, strings that both look like dates compare chronologically,
+    /// other strings compare lexically. Values of differing types are incomparable.
+    #[must_use]
+    pub fn compare(&self, fm_scalar: &serde_json::Value) -> Option<Ordering> {
+        match (fm_scalar, self) {
+            (serde_json::Value::Number(a), Scalar::Number(b)) => {
+                a.as_f64()?.partial_cmp(&b.as_f64()?)
+            }
+            (serde_json::Value::String(a), Scalar::String(b)) => {
+                match (parse_timestamp(a), parse_timestamp(b)) {
+                    (Some(a), Some(b)) => Some(a.cmp(&b)),
+                    _ => Some(a.cmp(b)),
+                }
+            }
+            (serde_json::Value::Bool(a), Scalar::Bool(b)) => Some(a.cmp(b)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct RawComparison {
+    #[serde(rename = "$gt", default)]
+    gt: Option<Scalar>,
+    #[serde(rename = "$gte", default)]
+    gte: Option<Scalar>,
+    #[serde(rename = "$lt", default)]
+    lt: Option<Scalar>,
+    #[serde(rename = "$lte", default)]
+    lte: Option<Scalar>,
+    #[serde(rename = "$ne", default)]
+    ne: Option<Scalar>,
+    #[serde(rename = "$exists", default)]
+    exists: Option<bool>,
+    #[serde(rename = "$regex", default)]
+    regex: Option<String>,
+    #[serde(rename = "$prefix", default)]
+    prefix: Option<String>,
+    #[serde(rename = "$contains", default)]
+    contains: Option<String>,
+    #[serde(rename = "$ignore_case", default)]
+    ignore_case: bool,
+}
+
+#[derive(Deserialize, Debug)]
+#[cfg_attr(debug_assertions, derive(Clone))]
+#[serde(try_from = "RawComparison")]
+pub struct Comparison {
+    pub gt: Option<Scalar>,
+    pub gte: Option<Scalar>,
+    pub lt: Option<Scalar>,
+    pub lte: Option<Scalar>,
+    pub ne: Option<Scalar>,
+    pub exists: Option<bool>,
+    pub regex: Option<Regex>,
+    /// Already case-folded if `ignore_case` is set
+    pub prefix: Option<String>,
+    /// Already case-folded if `ignore_case` is set
+    pub contains: Option<String>,
+    pub ignore_case: bool,
+}
+
+impl TryFrom<RawComparison> for Comparison {
+    type Error = regex::Error;
+
+    fn try_from(raw: RawComparison) -> Result<Self, Self::Error> {
+        // Compiled here so that it happens once per query, rather than once per file
+        let regex = raw
+            .regex
+            .map(|pattern| {
+                RegexBuilder::new(&pattern)
+                    .case_insensitive(raw.ignore_case)
+                    .build()
+            })
+            .transpose()?;
+        let fold = |s: String| if raw.ignore_case { s.to_lowercase() } else { s };
+        Ok(Self {
+            gt: raw.gt,
+            gte: raw.gte,
+            lt: raw.lt,
+            lte: raw.lte,
+            ne: raw.ne,
+            exists: raw.exists,
+            regex,
+            prefix: raw.prefix.map(fold),
+            contains: raw.contains.map(fold),
+            ignore_case: raw.ignore_case,
+        })
+    }
+}
+
+impl Comparison {
+    /// Every given operator must hold. Range operators only hold for comparable scalars and
+    /// string operators only hold for strings, whereas `$ne` holds for a sequence so long as
+    /// none of its elements are equal.
+    #[must_use]
+    pub fn matches(&self, fm_value: &serde_json::Value) -> bool {
+        if self.exists == Some(false) {
+            return false;
+        }
+
+        if let Some(ne) = &self.ne {
+            let is_equal = match fm_value {
+                serde_json::Value::Array(fm_vec) => fm_vec.iter().any(|fm| ne.matches(fm)),
+                fm_scalar => ne.matches(fm_scalar),
+            };
+            if is_equal {
+                return false;
+            }
+        }
+
+        let bounds = [
+            (&self.gt, Ordering::is_gt as fn(Ordering) -> bool),
+            (&self.gte, Ordering::is_ge),
+            (&self.lt, Ordering::is_lt),
+            (&self.lte, Ordering::is_le),
+        ];
+
+        let within_bounds = bounds.into_iter().all(|(bound, holds)| {
+            bound
+                .as_ref()
+                .is_none_or(|bound| bound.compare(fm_value).is_some_and(holds))
+        });
+
+        within_bounds && self.matches_text(fm_value)
+    }
+
+    fn matches_text(&self, fm_value: &serde_json::Value) -> bool {
+        if self.regex.is_none() && self.prefix.is_none() && self.contains.is_none() {
+            return true;
+        }
+
+        let serde_json::Value::String(fm_string) = fm_value else {
+            return false;
+        };
+
+        if self
+            .regex
+            .as_ref()
+            .is_some_and(|regex| !regex.is_match(fm_string))
+        {
+            return false;
+        }
+
+        let fm_string = if self.ignore_case {
+            Cow::Owned(fm_string.to_lowercase())
+        } else {
+            Cow::Borrowed(fm_string)
+        };
+
+        self.prefix
+            .as_ref()
+            .is_none_or(|prefix| fm_string.starts_with(prefix.as_str()))
+            && self
+                .contains
+                .as_ref()
+                .is_none_or(|contains| fm_string.contains(contains.as_str()))
+    }
+
+    /// An absent key only matches `$exists: false`
+    #[must_use]
+    pub fn matches_missing(&self) -> bool {
+        self.exists == Some(false)
+    }
 }
 
 #[derive(Deserialize, Debug)]
@@ -32,9 +203,18 @@ impl Scalar {
 pub enum QueryValue {
     Vec(Vec<Scalar>),
     Scalar(Scalar),
+    Comparison(Comparison),
 }
 
 impl QueryValue {
+    #[must_use]
+    pub fn matches_missing(&self) -> bool {
+        match self {
+            QueryValue::Comparison(comparison) => comparison.matches_missing(),
+            QueryValue::Vec(_) | QueryValue::Scalar(_) => false,
+        }
+    }
+
     #[must_use]
     pub fn is_subset(&self, fm_value: &serde_json::Value) -> bool {
         match (self, fm_value) {
@@ -42,6 +222,7 @@ impl QueryValue {
                 vec.iter().all(|s| fm_vec.iter().any(|fm| s.matches(fm)))
             }
             (QueryValue::Scalar(scalar), fm_scalar) => scalar.matches(fm_scalar),
+            (QueryValue::Comparison(comparison), fm_value) => comparison.matches(fm_value),
             _ => false,
         }
     }
@@ -56,6 +237,7 @@ impl QueryValue {
                 vec.iter().any(|s| fm_vec.iter().any(|fm| s.matches(fm)))
             }
             (QueryValue::Scalar(scalar), fm_scalar) => scalar.matches(fm_scalar),
+            (QueryValue::Comparison(comparison), fm_value) => comparison.matches(fm_value),
             _ => false,
         }
     }
@@ -65,18 +247,14 @@ impl QueryValue {
 #[cfg_attr(debug_assertions, derive(Clone))]
 pub struct FrontmatterQueryMap(pub HashMap<String, QueryValue>);
 
-#[derive(Debug, Deserialize)]
-#[cfg_attr(debug_assertions, derive(Clone))]
-pub struct FrontmatterQuery {
-    pub map: FrontmatterQueryMap,
-    pub intersect: bool,
-}
-
 impl FrontmatterQueryMap {
     #[must_use]
     pub fn is_subset(&self, json_frontmatter: &serde_json::Map<String, serde_json::Value>) -> bool {
         for (key, value) in &self.0 {
-            let Some(fm_value) = json_frontmatter.get(key) else {
+            let Some(fm_value) = key_path::get_json(json_frontmatter, key) else {
+                if value.matches_missing() {
+                    continue;
+                }
                 return false;
             };
 
@@ -94,7 +272,10 @@ impl FrontmatterQueryMap {
         json_frontmatter: &serde_json::Map<String, serde_json::Value>,
     ) -> bool {
         for (key, value) in &self.0 {
-            let Some(fm_value) = json_frontmatter.get(key) else {
+            let Some(fm_value) = key_path::get_json(json_frontmatter, key) else {
+                if value.matches_missing() {
+                    continue;
+                }
                 return false;
             };
 
@@ -112,11 +293,60 @@ impl FrontmatterQueryMap {
     }
 }
 
+/// A node in a query tree. A plain map requires every key to match, with sequences matching if
+/// they contain every given element. `$intersect` instead lets sequences match if they share any
+/// element, and `$and`, `$or` and `$not` combine other nodes.
+#[derive(Debug, Deserialize)]
+#[cfg_attr(debug_assertions, derive(Clone))]
+#[serde(untagged, deny_unknown_fields)]
+pub enum FrontmatterQuery {
+    And {
+        #[serde(rename = "$and")]
+        and: Vec<FrontmatterQuery>,
+    },
+    Or {
+        #[serde(rename = "$or")]
+        or: Vec<FrontmatterQuery>,
+    },
+    Not {
+        #[serde(rename = "$not")]
+        not: Box<FrontmatterQuery>,
+    },
+    Intersect {
+        #[serde(rename = "$intersect")]
+        intersect: FrontmatterQueryMap,
+    },
+    Subset(FrontmatterQueryMap),
+}
+
+impl FrontmatterQuery {
+    /// Files without frontmatter are treated as though their frontmatter were empty, so they
+    /// only match queries that are satisfied by missing keys.
+    #[must_use]
+    pub fn matches(
+        &self,
+        json_frontmatter: Option<&serde_json::Map<String, serde_json::Value>>,
+    ) -> bool {
+        let empty = serde_json::Map::new();
+        self.matches_map(json_frontmatter.unwrap_or(&empty))
+    }
+
+    fn matches_map(&self, json_frontmatter: &serde_json::Map<String, serde_json::Value>) -> bool {
+        match self {
+            FrontmatterQuery::And { and } => and.iter().all(|q| q.matches_map(json_frontmatter)),
+            FrontmatterQuery::Or { or } => or.iter().any(|q| q.matches_map(json_frontmatter)),
+            FrontmatterQuery::Not { not } => !not.matches_map(json_frontmatter),
+            FrontmatterQuery::Intersect { intersect: map } => map.is_intersect(json_frontmatter),
+            FrontmatterQuery::Subset(map) => map.is_subset(json_frontmatter),
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use serde_json::json;
 
-    use super::FrontmatterQueryMap;
+    use super::{FrontmatterQuery, FrontmatterQueryMap};
 
     macro_rules! deserial {
         ($tokens:tt) => {
@@ -163,12 +393,156 @@ mod test {
         })));
     }
 
+    #[test]
+    fn comparison() {
+        let frontmatter_query: FrontmatterQueryMap = deserial!({
+            "rating": { "$gte": 4 },
+            "date": { "$gt": "2024-01-01" }
+        });
+
+        assert!(frontmatter_query.is_subset(&deserial!({
+            "rating": 4,
+            "date": "2024-01-01T10:00:00Z"
+        })));
+
+        assert!(!frontmatter_query.is_subset(&deserial!({
+            "rating": 3.5,
+            "date": "2024-02-01"
+        })));
+
+        assert!(!frontmatter_query.is_subset(&deserial!({
+            "rating": 5,
+            "date": "2023-12-31"
+        })));
+
+        assert!(!frontmatter_query.is_subset(&deserial!({
+            "rating": "5",
+            "date": "2024-02-01"
+        })));
+    }
+
+    #[test]
+    fn exists() {
+        let frontmatter_query: FrontmatterQuery = deserial!({
+            "draft": { "$exists": false },
+            "cover_image": { "$exists": true }
+        });
+
+        assert!(frontmatter_query.matches(Some(&deserial!({
+            "cover_image": null
+        }))));
+
+        assert!(!frontmatter_query.matches(Some(&deserial!({
+            "cover_image": "cover.png",
+            "draft": false
+        }))));
+
+        assert!(!frontmatter_query.matches(Some(&deserial!({}))));
+
+        assert!(!frontmatter_query.matches(None));
+
+        let frontmatter_query: FrontmatterQuery = deserial!({
+            "draft": { "$exists": false }
+        });
+
+        assert!(
+            frontmatter_query.matches(None),
+            "no frontmatter is the same as empty frontmatter"
+        );
+    }
+
+    #[test]
+    fn nested_keys() {
+        let frontmatter_query: FrontmatterQueryMap = deserial!({
+            "author.handle": "teajey",
+            "/links/0/url": "https://example.com",
+            "series.part": { "$gte": 2 }
+        });
+
+        assert!(frontmatter_query.is_subset(&deserial!({
+            "author": { "name": "Tea", "handle": "teajey" },
+            "links": [{ "url": "https://example.com" }],
+            "series": { "name": "Rust", "part": 2 }
+        })));
+
+        assert!(!frontmatter_query.is_subset(&deserial!({
+            "author": { "name": "Tea", "handle": "teajey" },
+            "links": [{ "url": "https://elsewhere.com" }, { "url": "https://example.com" }],
+            "series": { "name": "Rust", "part": 2 }
+        })));
+
+        let frontmatter_query: FrontmatterQueryMap = deserial!({
+            "og.title": "Literal"
+        });
+
+        assert!(
+            frontmatter_query.is_subset(&deserial!({
+                "og.title": "Literal",
+                "og": { "title": "Nested" }
+            })),
+            "an exact top-level key takes precedence"
+        );
+    }
+
+    #[test]
+    fn combinators() {
+        let frontmatter_query: FrontmatterQuery = deserial!({
+            "$and": [
+                { "$or": [{ "tags": ["essay"] }, { "tags": ["film"] }] },
+                { "$not": { "draft": true } }
+            ]
+        });
+
+        assert!(frontmatter_query.matches(Some(&deserial!({
+            "tags": ["film", "review"]
+        }))));
+
+        assert!(frontmatter_query.matches(Some(&deserial!({
+            "tags": ["essay"],
+            "draft": false
+        }))));
+
+        assert!(!frontmatter_query.matches(Some(&deserial!({
+            "tags": ["essay"],
+            "draft": true
+        }))));
+
+        assert!(!frontmatter_query.matches(Some(&deserial!({
+            "tags": ["story"]
+        }))));
+
+        assert!(!frontmatter_query.matches(None));
+
+        let frontmatter_query: FrontmatterQuery = deserial!({
+            "$intersect": { "tags": ["essay", "film"] }
+        });
+
+        assert!(frontmatter_query.matches(Some(&deserial!({
+            "tags": ["film"]
+        }))));
+
+        let frontmatter_query: Result<FrontmatterQuery, _> = serde_json::from_value(json!({
+            "$not": { "draft": true },
+            "listed": true
+        }));
+        assert!(
+            frontmatter_query.is_err(),
+            "combinators can't be mixed with keys"
+        );
+    }
+
     mod query_value {
         use super::{
             super::{QueryValue, Scalar},
             json,
         };
 
+        macro_rules! deserial {
+            ($tokens:tt) => {
+                serde_json::from_value(json!($tokens)).unwrap()
+            };
+        }
+
         #[test]
         fn is_subset() {
             let a = QueryValue::Vec(vec![s!("dis")]);
@@ -203,5 +577,60 @@ mod test {
                 "empty set intersects with all other sets"
             );
         }
+
+        #[test]
+        fn comparison() {
+            let a: QueryValue = deserial!({ "$gt": 9, "$lt": 20 });
+            assert!(a.is_subset(&json!(10)), "numbers compare numerically");
+            assert!(!a.is_subset(&json!(9)), "lower bound is exclusive");
+            assert!(!a.is_subset(&json!(20)), "upper bound is exclusive");
+
+            let a: QueryValue = deserial!({ "$lte": "b" });
+            assert!(a.is_subset(&json!("apple")), "strings compare lexically");
+            assert!(!a.is_subset(&json!("cherry")));
+
+            let a: QueryValue = deserial!({ "$lt": "2024-03-01" });
+            assert!(
+                a.is_subset(&json!("2024-02-29 23:59:59")),
+                "dates compare chronologically"
+            );
+
+            let a: QueryValue = deserial!({ "$ne": "draft" });
+            assert!(a.is_subset(&json!("published")));
+            assert!(!a.is_subset(&json!("draft")));
+            assert!(a.is_subset(&json!(["essay", "film"])));
+            assert!(
+                !a.is_subset(&json!(["essay", "draft"])),
+                "no element of a sequence may be equal"
+            );
+
+            let a: Result<QueryValue, _> = serde_json::from_value(json!({ "$gt": 1, "x": 2 }));
+            assert!(a.is_err(), "unknown operators are rejected");
+        }
+
+        #[test]
+        fn text() {
+            let a: QueryValue = deserial!({ "$prefix": "2024-" });
+            assert!(a.is_subset(&json!("2024-01-14")));
+            assert!(!a.is_subset(&json!("2023-12-31")));
+            assert!(!a.is_subset(&json!(2024)), "only strings match");
+
+            let a: QueryValue = deserial!({ "$contains": "Rust" });
+            assert!(a.is_subset(&json!("Learning Rust in 2024")));
+            assert!(!a.is_subset(&json!("Learning rust in 2024")));
+
+            let a: QueryValue = deserial!({ "$contains": "Rust", "$ignore_case": true });
+            assert!(a.is_subset(&json!("Learning rust in 2024")));
+
+            let a: QueryValue = deserial!({ "$regex": "^chapter-\\d+$" });
+            assert!(a.is_subset(&json!("chapter-12")));
+            assert!(!a.is_subset(&json!("Chapter-12")));
+
+            let a: QueryValue = deserial!({ "$regex": "^chapter-\\d+$", "$ignore_case": true });
+            assert!(a.is_subset(&json!("Chapter-12")));
+
+            let a: Result<QueryValue, _> = serde_json::from_value(json!({ "$regex": "(" }));
+            assert!(a.is_err(), "invalid regexes are rejected");
+        }
     }
 }
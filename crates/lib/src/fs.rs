@@ -1,29 +1,36 @@
 use camino::{Utf8Path as Path, Utf8PathBuf as PathBuf};
 
-pub fn path_has_extensions(path: &Path, extensions: &[&str]) -> bool {
-    path.extension()
-        .is_some_and(|ext| extensions.contains(&ext))
+use crate::filter::Filter;
+
+/// The name a file is keyed by: its path relative to `root`, always `/`-separated.
+pub fn relative_name(root: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(root).ok()?;
+    if relative.as_str().is_empty() {
+        return None;
+    }
+    Some(
+        relative
+            .components()
+            .map(|component| component.as_str())
+            .collect::<Vec<_>>()
+            .join("/"),
+    )
 }
 
-pub fn filepaths_with_extensions(
-    dir: &Path,
-    extensions: &[&str],
-) -> Result<Vec<PathBuf>, std::io::Error> {
-    dir.read_dir_utf8()?
-        .filter_map(|entry| {
-            entry
-                .map(|entry| {
-                    let path = entry.path().to_path_buf();
-                    if !path.is_file() {
-                        return None;
-                    }
-                    if path_has_extensions(&path, extensions) {
-                        Some(path)
-                    } else {
-                        None
-                    }
-                })
-                .transpose()
-        })
-        .collect()
+/// Every file beneath `dir` that `filter` includes. Ignored directories aren't descended into.
+pub fn filepaths(root: &Path, dir: &Path, filter: &Filter) -> Result<Vec<PathBuf>, std::io::Error> {
+    let mut paths = Vec::new();
+    for entry in dir.read_dir_utf8()? {
+        let entry = entry?;
+        let path = entry.path().to_path_buf();
+        // Symlinked directories aren't followed so that a link cycle can't recurse forever
+        if entry.file_type()?.is_dir() {
+            if filter.includes_dir(root, &path) {
+                paths.extend(filepaths(root, &path, filter)?);
+            }
+        } else if path.is_file() && filter.includes_file(root, &path) {
+            paths.push(path);
+        }
+    }
+    Ok(paths)
 }
@@ -0,0 +1,12 @@
+use tracing::debug;
+
+use crate::frontmatter_file::{keeper::LoadError, Keeper};
+
+/// Files that couldn't be loaded, so that their authors can fix them
+#[must_use]
+pub fn errors(keeper: &Keeper) -> Vec<LoadError> {
+    debug!("Received errors request");
+    let response = keeper.errors().cloned().collect();
+    debug!("Sending errors response: {response:?}");
+    response
+}
@@ -0,0 +1,52 @@
+/// Splits a nested key like `author.handle`, or a JSON pointer like `/links/0/url`, into its
+/// segments. Keys with nothing to descend into give `None`.
+fn segments(key: &str) -> Option<Vec<String>> {
+    if let Some(pointer) = key.strip_prefix('/') {
+        return Some(
+            pointer
+                .split('/')
+                .map(|segment| segment.replace("~1", "/").replace("~0", "~"))
+                .collect(),
+        );
+    }
+    if key.contains('.') {
+        return Some(key.split('.').map(ToOwned::to_owned).collect());
+    }
+    None
+}
+
+/// Looks up `key` in JSON frontmatter, preferring a top-level key of that exact name before
+/// descending into nested mappings and sequences.
+pub fn get_json<'a>(
+    frontmatter: &'a serde_json::Map<String, serde_json::Value>,
+    key: &str,
+) -> Option<&'a serde_json::Value> {
+    if let Some(value) = frontmatter.get(key) {
+        return Some(value);
+    }
+    let segments = segments(key)?;
+    let (first, rest) = segments.split_first()?;
+    rest.iter()
+        .try_fold(frontmatter.get(first)?, |value, segment| match value {
+            serde_json::Value::Array(seq) => seq.get(segment.parse::<usize>().ok()?),
+            value => value.get(segment),
+        })
+}
+
+/// Looks up `key` in YAML frontmatter, preferring a top-level key of that exact name before
+/// descending into nested mappings and sequences.
+pub fn get_yaml<'a>(
+    frontmatter: &'a serde_yaml::Mapping,
+    key: &str,
+) -> Option<&'a serde_yaml::Value> {
+    if let Some(value) = frontmatter.get(key) {
+        return Some(value);
+    }
+    let segments = segments(key)?;
+    let (first, rest) = segments.split_first()?;
+    rest.iter()
+        .try_fold(frontmatter.get(first)?, |value, segment| match value {
+            serde_yaml::Value::Sequence(seq) => seq.get(segment.parse::<usize>().ok()?),
+            value => value.get(segment),
+        })
+}
@@ -1,51 +1,100 @@
 #![allow(clippy::missing_errors_doc)]
 
+pub mod changes;
 pub mod collate;
+pub mod config;
+pub mod excerpt;
+pub mod filter;
 pub mod frontmatter_file;
+mod frontmatter_index;
 pub mod frontmatter_query;
 mod fs;
+pub mod health;
+mod key_path;
+pub mod links;
 pub mod list;
 mod markup;
+pub mod outline;
+#[cfg(feature = "html")]
+pub mod render;
+pub mod search;
 pub mod single;
+pub mod sort;
 
-use serde_yaml::Mapping;
+use chrono::{DateTime, Utc};
 
-use frontmatter_file::FrontmatterFile;
+use frontmatter_file::{FrontmatterFile, JsonFrontmatter, Keeper};
 use frontmatter_query::FrontmatterQuery;
+use sort::{Position, SortSpec, SortValue};
 
+/// Frontmatter values take precedence, but `name`, `created` and `modified` otherwise refer to
+/// the file's own attributes. Anything else that's missing sorts like a null.
 fn get_sort_value(
-    frontmatter: Option<&Mapping>,
-    created: &chrono::DateTime<chrono::Utc>,
+    frontmatter: Option<&JsonFrontmatter>,
+    name: &str,
+    created: &DateTime<Utc>,
+    modified: &DateTime<Utc>,
     sort_key: &str,
-) -> String {
-    frontmatter
-        .and_then(|m| m.get(sort_key))
-        .map(serde_yaml::to_string)
-        .transpose()
-        .ok()
-        .flatten()
-        .unwrap_or_else(|| serde_yaml::to_string(created).expect("DateTime<Utc> must serialize"))
+) -> Option<SortValue> {
+    if let Some(value) = frontmatter.and_then(|m| key_path::get_json(m, sort_key)) {
+        return SortValue::from_json(value);
+    }
+    match sort_key {
+        "name" => Some(SortValue::String(name.to_owned())),
+        "created" => Some(SortValue::Timestamp(*created)),
+        "modified" => Some(SortValue::Timestamp(*modified)),
+        _ => None,
+    }
 }
 
+fn get_sort_position(
+    frontmatter: Option<&JsonFrontmatter>,
+    name: &str,
+    created: &DateTime<Utc>,
+    modified: &DateTime<Utc>,
+    sort: &[SortSpec<'_>],
+) -> Position {
+    let values = if sort.is_empty() {
+        vec![Some(SortValue::Timestamp(*created))]
+    } else {
+        sort.iter()
+            .map(|spec| get_sort_value(frontmatter, name, created, modified, spec.key))
+            .collect()
+    };
+    Position {
+        values,
+        name: name.to_owned(),
+    }
+}
+
+/// Files matching `query`, along with the file called `name`. Only the files that the
+/// frontmatter index can't rule out are checked.
 fn query_files<'a>(
-    files: impl Iterator<Item = &'a FrontmatterFile>,
+    keeper: &'a Keeper,
     query: FrontmatterQuery,
     name: Option<&'a str>,
 ) -> impl Iterator<Item = &'a FrontmatterFile> {
+    let files: Box<dyn Iterator<Item = &'a FrontmatterFile>> =
+        match keeper.frontmatter_index().candidates(&query) {
+            Some(mut paths) => {
+                if let Some(name) = name {
+                    paths.extend(
+                        keeper
+                            .iter()
+                            .filter(|(_, file)| file.name() == name)
+                            .map(|(path, _)| path),
+                    );
+                }
+                Box::new(paths.into_iter().filter_map(|path| keeper.get(path)))
+            }
+            None => Box::new(keeper.files()),
+        };
     files.filter(move |file| {
         if let Some(name) = name {
-            if file.name == name {
+            if file.name() == name {
                 return true;
             }
         }
-        let Some(frontmatter) = file.frontmatter() else {
-            // if query is '{}', include this
-            return query.map.is_empty();
-        };
-        if query.intersect {
-            query.map.is_intersect(&markup::yaml_to_json(frontmatter))
-        } else {
-            query.map.is_subset(&markup::yaml_to_json(frontmatter))
-        }
+        query.matches(file.json_frontmatter())
     })
 }
@@ -0,0 +1,585 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use pulldown_cmark::{Event, LinkType, Tag};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::filter::Filter;
+use crate::frontmatter_file::Keeper;
+use crate::markup;
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkKind {
+    /// `[[note]]`, `[[folder/note|alias]]` or `[[note#heading]]`
+    Wiki,
+    /// `[text](../note.md)`
+    Markdown,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct RawLink {
+    kind: LinkKind,
+    target: String,
+}
+
+/// Links to other files in `body`. External URLs and links within the same file are left out.
+fn extract(body: &str) -> Vec<RawLink> {
+    markup::markdown_parser(body)
+        .filter_map(|event| {
+            let Event::Start(Tag::Link {
+                link_type,
+                dest_url,
+                ..
+            }) = event
+            else {
+                return None;
+            };
+            let kind = match link_type {
+                LinkType::WikiLink { .. } => LinkKind::Wiki,
+                LinkType::Autolink | LinkType::Email => return None,
+                _ if dest_url.contains("://") || dest_url.starts_with("mailto:") => return None,
+                _ => LinkKind::Markdown,
+            };
+            let (path, _fragment) = dest_url.split_once('#').unwrap_or((&dest_url, ""));
+            if path.trim().is_empty() {
+                return None;
+            }
+            Some(RawLink {
+                kind,
+                target: dest_url.to_string(),
+            })
+        })
+        .collect()
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).unwrap_or_else(|_| s.to_owned())
+}
+
+/// Joins `path` onto `dir`, resolving `.` and `..`. Paths that climb out of the root give `None`.
+fn join(dir: &str, path: &str) -> Option<String> {
+    let mut segments = Vec::new();
+    let base = if path.starts_with('/') { "" } else { dir };
+    for segment in base.split('/').chain(path.split('/')) {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop()?;
+            }
+            segment => segments.push(segment),
+        }
+    }
+    Some(segments.join("/"))
+}
+
+fn file_name(name: &str) -> &str {
+    name.rsplit('/').next().unwrap_or(name)
+}
+
+fn dir(name: &str) -> &str {
+    name.rsplit_once('/').map_or("", |(dir, _)| dir)
+}
+
+/// The part of `link` that names a file, without any heading
+fn target_path(link: &RawLink) -> &str {
+    link.target.split('#').next().unwrap_or_default().trim()
+}
+
+/// The names of the files that links can point at. Links are resolved the way Obsidian does:
+/// wikilinks by path from the root or by file name anywhere, markdown links relative to the file
+/// they're in. A link may leave out the extension of the file it points at.
+#[derive(Debug)]
+struct Names {
+    /// The extensions of the files served, without a leading `.`
+    extensions: Vec<String>,
+    names: HashSet<String>,
+    by_stem: HashMap<String, BTreeSet<String>>,
+}
+
+impl Names {
+    fn new(extensions: &[String]) -> Self {
+        Self {
+            extensions: extensions.to_vec(),
+            names: HashSet::new(),
+            by_stem: HashMap::new(),
+        }
+    }
+
+    /// `name` without its directory or any of the extensions served
+    fn stem<'a>(&self, name: &'a str) -> &'a str {
+        let file_name = file_name(name);
+        self.extensions
+            .iter()
+            .find_map(|extension| {
+                file_name
+                    .strip_suffix(extension.as_str())
+                    .and_then(|stem| stem.strip_suffix('.'))
+            })
+            .unwrap_or(file_name)
+    }
+
+    /// The file names and stems of the files that `link` could resolve to
+    fn keys(&self, link: &RawLink) -> [String; 2] {
+        let path = match link.kind {
+            LinkKind::Markdown => percent_decode(target_path(link)),
+            LinkKind::Wiki => target_path(link).to_owned(),
+        };
+        [file_name(&path).to_owned(), self.stem(&path).to_owned()]
+    }
+
+    /// Whether `name` is new
+    fn insert(&mut self, name: &str) -> bool {
+        if !self.names.insert(name.to_owned()) {
+            return false;
+        }
+        self.by_stem
+            .entry(self.stem(name).to_owned())
+            .or_default()
+            .insert(name.to_owned());
+        true
+    }
+
+    /// Whether `name` was there
+    fn remove(&mut self, name: &str) -> bool {
+        if !self.names.remove(name) {
+            return false;
+        }
+        let stem = self.stem(name);
+        if let Some(names) = self.by_stem.get_mut(stem) {
+            names.remove(name);
+            if names.is_empty() {
+                self.by_stem.remove(stem);
+            }
+        }
+        true
+    }
+
+    fn existing(&self, candidate: &str) -> Option<&str> {
+        self.names
+            .get(candidate)
+            .or_else(|| {
+                self.extensions
+                    .iter()
+                    .find_map(|extension| self.names.get(&format!("{candidate}.{extension}")))
+            })
+            .map(String::as_str)
+    }
+
+    fn resolve(&self, from: &str, link: &RawLink) -> Option<&str> {
+        let target = target_path(link);
+        match link.kind {
+            LinkKind::Markdown => self.existing(&join(dir(from), &percent_decode(target))?),
+            LinkKind::Wiki if target.contains('/') => join("", target)
+                .and_then(|path| self.existing(&path))
+                .or_else(|| self.existing(&join(dir(from), target)?)),
+            LinkKind::Wiki => {
+                let candidates = self.by_stem.get(self.stem(target))?;
+                candidates
+                    .iter()
+                    .find(|name| dir(name) == dir(from))
+                    .or_else(|| candidates.first())
+                    .map(String::as_str)
+            }
+        }
+    }
+}
+
+/// The links between files, resolved as files load and kept up to date by [`Keeper`], so that a
+/// link starts working as soon as its target appears
+#[derive(Debug)]
+pub struct Graph {
+    names: Names,
+    /// The links in each file, with the file each one resolves to
+    outgoing: HashMap<String, Vec<(RawLink, Option<String>)>>,
+    /// The files linking to each file
+    backlinks: HashMap<String, BTreeSet<String>>,
+    /// The files with links that could resolve to files with this file name or stem
+    linking: HashMap<String, HashSet<String>>,
+}
+
+impl Default for Graph {
+    fn default() -> Self {
+        Self::new(Filter::default().extensions())
+    }
+}
+
+impl Graph {
+    /// A graph of files with any of `extensions`
+    pub(crate) fn new(extensions: &[String]) -> Self {
+        Self {
+            names: Names::new(extensions),
+            outgoing: HashMap::new(),
+            backlinks: HashMap::new(),
+            linking: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn insert(&mut self, name: &str, body: &str) {
+        self.remove_links(name);
+        let links = extract(body);
+        for link in &links {
+            for key in self.names.keys(link) {
+                self.linking.entry(key).or_default().insert(name.to_owned());
+            }
+        }
+        self.outgoing.insert(
+            name.to_owned(),
+            links.into_iter().map(|link| (link, None)).collect(),
+        );
+        if self.names.insert(name) {
+            self.relink_affected(name);
+        }
+        self.relink(name);
+    }
+
+    pub(crate) fn remove(&mut self, name: &str) {
+        self.remove_links(name);
+        if self.names.remove(name) {
+            self.relink_affected(name);
+        }
+    }
+
+    fn remove_links(&mut self, name: &str) {
+        let Some(links) = self.outgoing.remove(name) else {
+            return;
+        };
+        for (link, to) in links {
+            for key in self.names.keys(&link) {
+                if let Some(linking) = self.linking.get_mut(&key) {
+                    linking.remove(name);
+                    if linking.is_empty() {
+                        self.linking.remove(&key);
+                    }
+                }
+            }
+            if let Some(to) = to {
+                self.remove_backlink(&to, name);
+            }
+        }
+    }
+
+    fn remove_backlink(&mut self, to: &str, from: &str) {
+        if let Some(backlinks) = self.backlinks.get_mut(to) {
+            backlinks.remove(from);
+            if backlinks.is_empty() {
+                self.backlinks.remove(to);
+            }
+        }
+    }
+
+    /// Resolves the links that might now point somewhere else, as `name` has come or gone
+    fn relink_affected(&mut self, name: &str) {
+        let affected = [file_name(name), self.names.stem(name)]
+            .into_iter()
+            .filter_map(|key| self.linking.get(key))
+            .flatten()
+            .cloned()
+            .collect::<HashSet<_>>();
+        for from in affected {
+            self.relink(&from);
+        }
+    }
+
+    /// Resolves the links in `from` again
+    fn relink(&mut self, from: &str) {
+        let Some(links) = self.outgoing.get_mut(from) else {
+            return;
+        };
+        let mut before = HashSet::new();
+        let mut after = HashSet::new();
+        for (link, to) in links.iter_mut() {
+            before.extend(to.take());
+            *to = self.names.resolve(from, link).map(ToOwned::to_owned);
+            after.extend(to.clone());
+        }
+        for to in before.difference(&after) {
+            self.remove_backlink(to, from);
+        }
+        for to in after.difference(&before) {
+            self.backlinks
+                .entry(to.clone())
+                .or_default()
+                .insert(from.to_owned());
+        }
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct Link<'a> {
+    pub kind: LinkKind,
+    /// The link as written
+    pub target: &'a str,
+    /// The file linked to, or `None` if the link is broken
+    pub name: Option<&'a str>,
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct BrokenLink {
+    /// The file the link is in
+    pub from: String,
+    pub kind: LinkKind,
+    pub target: String,
+}
+
+/// The links in the file called `name`, and the files that link to it
+pub(crate) fn for_file<'a>(keeper: &'a Keeper, name: &str) -> (Vec<Link<'a>>, Vec<&'a str>) {
+    let graph = keeper.link_graph();
+    let links = graph
+        .outgoing
+        .get(name)
+        .into_iter()
+        .flatten()
+        .map(|(link, to)| Link {
+            kind: link.kind,
+            target: &link.target,
+            name: to.as_deref(),
+        })
+        .collect();
+    let backlinks = graph
+        .backlinks
+        .get(name)
+        .into_iter()
+        .flatten()
+        .map(String::as_str)
+        .collect();
+    (links, backlinks)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Args<'a> {
+    pub name: &'a str,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Response<'a> {
+    pub links: Vec<Link<'a>>,
+    pub backlinks: Vec<&'a str>,
+}
+
+#[must_use]
+#[allow(clippy::needless_pass_by_value)]
+pub fn links<'a>(keeper: &'a Keeper, args: Args<'_>) -> Option<Response<'a>> {
+    debug!("Received links request: {args:?}");
+    let response = keeper
+        .files()
+        .any(|file| file.name() == args.name)
+        .then(|| {
+            let (links, backlinks) = for_file(keeper, args.name);
+            Response { links, backlinks }
+        });
+    debug!("Sending links response: {response:?}");
+    response
+}
+
+/// Every link that doesn't point at a file, ordered by the file it's in
+#[must_use]
+pub fn broken(keeper: &Keeper) -> Vec<BrokenLink> {
+    debug!("Received broken links request");
+    let response = keeper
+        .link_graph()
+        .outgoing
+        .iter()
+        .collect::<BTreeMap<_, _>>()
+        .into_iter()
+        .flat_map(|(from, links)| {
+            links
+                .iter()
+                .filter(|(_, to)| to.is_none())
+                .map(|(link, _)| BrokenLink {
+                    from: from.clone(),
+                    kind: link.kind,
+                    target: link.target.clone(),
+                })
+        })
+        .collect();
+    debug!("Sending broken links response: {response:?}");
+    response
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use camino::Utf8PathBuf;
+    use chrono::TimeZone;
+    use pretty_assertions::assert_eq;
+
+    use super::{BrokenLink, Link, LinkKind};
+    use crate::frontmatter_file::{FrontmatterFile, Keeper};
+
+    fn file(name: &str, body: &str) -> (Utf8PathBuf, FrontmatterFile) {
+        let dt = chrono::Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        (
+            Utf8PathBuf::from(format!("/{name}")),
+            FrontmatterFile::new(name.to_owned(), None, body.to_owned(), dt, dt),
+        )
+    }
+
+    fn make_test_keeper() -> Keeper {
+        Keeper::from_files(
+            Utf8PathBuf::from("/"),
+            HashMap::from([
+                file(
+                    "index.md",
+                    "See [[ideas]], [[notes/ideas|my ideas]] and [[missing]].",
+                ),
+                file(
+                    "notes/ideas.md",
+                    "Back to [home](../index.md#top), [out](https://example.com) or [[#here]].",
+                ),
+                file(
+                    "notes/todo.md",
+                    "Same folder: [[ideas]], and [gone](gone%20away.md).",
+                ),
+                file("ideas.md", "Nothing links out."),
+            ]),
+        )
+    }
+
+    #[test]
+    fn resolve() {
+        let keeper = make_test_keeper();
+
+        let response = super::links(&keeper, super::Args { name: "index.md" }).unwrap();
+        assert_eq!(
+            vec![
+                Link {
+                    kind: LinkKind::Wiki,
+                    target: "ideas",
+                    name: Some("ideas.md"),
+                },
+                Link {
+                    kind: LinkKind::Wiki,
+                    target: "notes/ideas",
+                    name: Some("notes/ideas.md"),
+                },
+                Link {
+                    kind: LinkKind::Wiki,
+                    target: "missing",
+                    name: None,
+                },
+            ],
+            response.links
+        );
+
+        let response = super::links(
+            &keeper,
+            super::Args {
+                name: "notes/ideas.md",
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            vec![Link {
+                kind: LinkKind::Markdown,
+                target: "../index.md#top",
+                name: Some("index.md"),
+            }],
+            response.links
+        );
+        assert_eq!(vec!["index.md", "notes/todo.md"], response.backlinks);
+
+        assert!(super::links(&keeper, super::Args { name: "nope.md" }).is_none());
+    }
+
+    #[test]
+    fn broken() {
+        let mut keeper = make_test_keeper();
+
+        assert_eq!(
+            vec![
+                BrokenLink {
+                    from: "index.md".to_owned(),
+                    kind: LinkKind::Wiki,
+                    target: "missing".to_owned(),
+                },
+                BrokenLink {
+                    from: "notes/todo.md".to_owned(),
+                    kind: LinkKind::Markdown,
+                    target: "gone%20away.md".to_owned(),
+                },
+            ],
+            super::broken(&keeper)
+        );
+
+        let (path, missing) = file("missing.md", "");
+        keeper.insert(path, missing);
+        let (path, gone) = file("notes/gone away.md", "");
+        keeper.insert(path, gone);
+        assert_eq!(Vec::<BrokenLink>::new(), super::broken(&keeper));
+    }
+
+    #[test]
+    fn backlinks_follow_changes() {
+        let mut keeper = make_test_keeper();
+        fn backlinks<'a>(keeper: &'a Keeper, name: &str) -> Vec<&'a str> {
+            super::for_file(keeper, name).1
+        }
+
+        assert_eq!(vec!["index.md"], backlinks(&keeper, "ideas.md"));
+
+        keeper.remove(&Utf8PathBuf::from("/ideas.md"));
+        assert_eq!(
+            vec!["index.md", "notes/todo.md"],
+            backlinks(&keeper, "notes/ideas.md"),
+            "[[ideas]] falls back to the only file left with that name"
+        );
+
+        let (path, ideas) = file("ideas.md", "Back again");
+        keeper.insert(path, ideas);
+        assert_eq!(vec!["index.md"], backlinks(&keeper, "ideas.md"));
+
+        let (path, index) = file("index.md", "Only [[notes/todo]] now.");
+        keeper.insert(path, index);
+        assert_eq!(Vec::<&str>::new(), backlinks(&keeper, "ideas.md"));
+        assert_eq!(vec!["notes/todo.md"], backlinks(&keeper, "notes/ideas.md"));
+        assert_eq!(vec!["index.md"], backlinks(&keeper, "notes/todo.md"));
+    }
+
+    #[test]
+    fn extensions() {
+        let mut graph = super::Graph::new(&["markdown".to_owned(), "txt".to_owned()]);
+        graph.insert(
+            "index.markdown",
+            "[[ideas]], [[todo.txt]], [todo](todo) and [[other.md]]",
+        );
+        graph.insert("ideas.markdown", "");
+        graph.insert("todo.txt", "");
+        graph.insert("other.md.txt", "");
+
+        let targets = graph.outgoing["index.markdown"]
+            .iter()
+            .map(|(_, to)| to.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                Some("ideas.markdown"),
+                Some("todo.txt"),
+                Some("todo.txt"),
+                Some("other.md.txt"),
+            ],
+            targets,
+            "links resolve with any of the extensions served"
+        );
+    }
+}
@@ -1,87 +1,179 @@
+mod cursor;
+
 use serde::{Deserialize, Serialize};
 use tracing::debug;
 
-use crate::frontmatter_file::{self, Keeper, Short};
-use crate::frontmatter_query::{FrontmatterQuery, FrontmatterQueryMap};
-use crate::{get_sort_value, query_files};
-
-fn sort_with_params(sort_key: Option<&str>, order_desc: bool, files: &mut [Short]) {
-    if let Some(sort_key) = sort_key {
-        files.sort_by(|f, g| {
-            let f_value = get_sort_value(f.frontmatter.as_ref(), &f.created, sort_key);
-            let g_value = get_sort_value(g.frontmatter.as_ref(), &g.created, sort_key);
-            f_value.cmp(&g_value)
-        });
-    } else {
-        files.sort();
-    }
+use crate::excerpt::Excerpt;
+use crate::frontmatter_file::{self, FrontmatterFile, Keeper, Projected, Short};
+use crate::frontmatter_query::FrontmatterQuery;
+use crate::sort::{self, Position, SortSpec};
+use crate::{get_sort_position, query_files};
 
-    if order_desc {
-        files.reverse();
-    }
+pub use cursor::CursorError;
+
+fn sort_with_params<'a>(
+    sort: &[SortSpec<'_>],
+    order_desc: bool,
+    nulls_first: bool,
+    files: Vec<&'a FrontmatterFile>,
+) -> Vec<(Position, &'a FrontmatterFile)> {
+    let mut positioned = files
+        .into_iter()
+        .map(|file| {
+            let position = get_sort_position(
+                file.json_frontmatter(),
+                file.name(),
+                file.created(),
+                file.modified(),
+                sort,
+            );
+            (position, file)
+        })
+        .collect::<Vec<_>>();
+
+    positioned
+        .sort_by(|(a, _), (b, _)| sort::compare_positions(a, b, sort, order_desc, nulls_first));
+
+    positioned
 }
 
-fn paginate(files: Vec<Short>, offset: Option<usize>, limit: Option<usize>) -> Vec<Short> {
-    match (offset, limit) {
-        (None, None) => files,
-        (None, Some(limit)) => files.into_iter().take(limit).collect(),
-        (Some(offset), None) => files.into_iter().skip(offset).collect(),
-        (Some(offset), Some(limit)) => files.into_iter().skip(offset).take(limit).collect(),
-    }
+/// Cursors take precedence over `offset`, which is then counted from the cursor. With only a
+/// `before` cursor, `limit` takes the files immediately preceding it.
+fn paginate(
+    files: &[(Position, &FrontmatterFile)],
+    args: &Args<'_>,
+) -> Result<(usize, usize), CursorError> {
+    let compare = |a: &Position, b: &Position| {
+        sort::compare_positions(a, b, &args.sort, args.order_desc, args.nulls_first)
+    };
+    let value_count = sort::directions(&args.sort, args.order_desc).count();
+    let after = args
+        .after
+        .map(|after| cursor::decode(after, value_count))
+        .transpose()?;
+    let before = args
+        .before
+        .map(|before| cursor::decode(before, value_count))
+        .transpose()?;
+
+    let start = after.as_ref().map_or(0, |after| {
+        files.partition_point(|(position, _)| compare(position, after).is_le())
+    });
+    let end = before
+        .as_ref()
+        .map_or(files.len(), |before| {
+            files.partition_point(|(position, _)| compare(position, before).is_lt())
+        })
+        .max(start);
+    let start = start
+        .saturating_add(args.offset.unwrap_or_default())
+        .min(end);
+
+    let range = match args.limit {
+        Some(limit) if before.is_some() && after.is_none() => {
+            (end.saturating_sub(limit).max(start), end)
+        }
+        Some(limit) => (start, start.saturating_add(limit).min(end)),
+        None => (start, end),
+    };
+
+    Ok(range)
 }
 
 #[derive(Debug, Deserialize)]
 pub struct Get<'a> {
-    #[serde(default)]
-    pub sort_key: Option<&'a str>,
+    #[serde(default, borrow)]
+    pub sort: Vec<SortSpec<'a>>,
     #[serde(default)]
     pub order_desc: bool,
     #[serde(default)]
+    pub nulls_first: bool,
+    #[serde(default)]
     pub offset: Option<usize>,
     #[serde(default)]
     pub limit: Option<usize>,
+    #[serde(default)]
+    pub after: Option<&'a str>,
+    #[serde(default)]
+    pub before: Option<&'a str>,
+    /// Only include these fields of each file. See [`Short::project`].
+    #[serde(default)]
+    pub fields: Option<Vec<&'a str>>,
+    #[serde(default)]
+    pub excerpt: Option<Excerpt>,
+    /// Include the headings of each file
+    #[serde(default)]
+    pub outline: bool,
 }
 
 impl<'a> Get<'a> {
     #[must_use]
     pub fn new(
-        sort_key: Option<&'a str>,
+        sort: Vec<SortSpec<'a>>,
         order_desc: bool,
+        nulls_first: bool,
         offset: Option<usize>,
         limit: Option<usize>,
     ) -> Self {
         Self {
-            sort_key,
+            sort,
             order_desc,
+            nulls_first,
             offset,
             limit,
+            after: None,
+            before: None,
+            fields: None,
+            excerpt: None,
+            outline: false,
+        }
+    }
+}
+
+impl<'a> From<Get<'a>> for Args<'a> {
+    fn from(
+        Get {
+            sort,
+            order_desc,
+            nulls_first,
+            offset,
+            limit,
+            after,
+            before,
+            fields,
+            excerpt,
+            outline,
+        }: Get<'a>,
+    ) -> Self {
+        Self {
+            query: None,
+            sort,
+            order_desc,
+            nulls_first,
+            offset,
+            limit,
+            after,
+            before,
+            fields,
+            excerpt,
+            outline,
         }
     }
 }
 
 #[derive(Serialize, Debug)]
 pub struct Response {
-    pub files: Vec<frontmatter_file::Short>,
+    pub files: Vec<frontmatter_file::Projected>,
     pub total: usize,
+    /// Continues from the last file in `files`, if there are any more
+    pub next_cursor: Option<String>,
+    /// Continues back from the first file in `files`, if there are any before it
+    pub prev_cursor: Option<String>,
 }
 
-#[allow(clippy::needless_pass_by_value)]
-fn inner_get(keeper: &Keeper, args: Get<'_>) -> Response {
-    let mut files = keeper.files().cloned().map(Short::from).collect::<Vec<_>>();
-
-    let total = files.len();
-
-    sort_with_params(args.sort_key, args.order_desc, &mut files);
-
-    let files = paginate(files, args.offset, args.limit);
-
-    Response { files, total }
-}
-
-#[allow(clippy::needless_pass_by_value)]
-pub fn get(keeper: &Keeper, args: Get<'_>) -> Response {
+pub fn get(keeper: &Keeper, args: Get<'_>) -> Result<Response, CursorError> {
     debug!("Received get request: {args:?}");
-    let response = inner_get(keeper, args);
+    let response = inner_query(keeper, args.into());
     debug!("Sending get response: {response:?}");
     response
 }
@@ -90,77 +182,275 @@ pub fn get(keeper: &Keeper, args: Get<'_>) -> Response {
 pub struct Args<'a> {
     #[serde(default)]
     pub query: Option<FrontmatterQuery>,
-    #[serde(default)]
-    pub sort_key: Option<&'a str>,
+    #[serde(default, borrow)]
+    pub sort: Vec<SortSpec<'a>>,
     #[serde(default)]
     pub order_desc: bool,
     #[serde(default)]
+    pub nulls_first: bool,
+    #[serde(default)]
     pub offset: Option<usize>,
     #[serde(default)]
     pub limit: Option<usize>,
+    #[serde(default)]
+    pub after: Option<&'a str>,
+    #[serde(default)]
+    pub before: Option<&'a str>,
+    /// Only include these fields of each file. See [`Short::project`].
+    #[serde(default)]
+    pub fields: Option<Vec<&'a str>>,
+    #[serde(default)]
+    pub excerpt: Option<Excerpt>,
+    /// Include the headings of each file
+    #[serde(default)]
+    pub outline: bool,
 }
 
 impl<'a> Args<'a> {
     #[must_use]
     pub fn get(
-        sort_key: Option<&'a str>,
+        sort: Vec<SortSpec<'a>>,
         order_desc: bool,
+        nulls_first: bool,
         offset: Option<usize>,
         limit: Option<usize>,
     ) -> Self {
-        Self {
-            query: None,
-            sort_key,
-            order_desc,
-            offset,
-            limit,
-        }
+        Get::new(sort, order_desc, nulls_first, offset, limit).into()
     }
 
     #[must_use]
     pub fn query(
-        map: FrontmatterQueryMap,
-        sort_key: Option<&'a str>,
+        query: FrontmatterQuery,
+        sort: Vec<SortSpec<'a>>,
         order_desc: bool,
+        nulls_first: bool,
         offset: Option<usize>,
         limit: Option<usize>,
-        intersect: bool,
     ) -> Self {
         Self {
-            query: Some(FrontmatterQuery { map, intersect }),
-            sort_key,
-            order_desc,
-            offset,
-            limit,
+            query: Some(query),
+            ..Self::get(sort, order_desc, nulls_first, offset, limit)
+        }
+    }
+
+    /// Only return files after and/or before the files that the cursors were taken from
+    #[must_use]
+    pub fn cursors(self, after: Option<&'a str>, before: Option<&'a str>) -> Self {
+        Self {
+            after,
+            before,
+            ..self
         }
     }
-}
 
-fn inner_query(keeper: &Keeper, args: Args<'_>) -> Response {
-    let files = keeper.files();
+    /// Only include `fields` of each file, rather than all of them
+    #[must_use]
+    pub fn fields(self, fields: Option<Vec<&'a str>>) -> Self {
+        Self { fields, ..self }
+    }
 
-    let mut files = if let Some(query) = args.query {
-        query_files(files, query, None)
-            .cloned()
-            .map(Short::from)
-            .collect::<Vec<_>>()
+    /// Cut an excerpt from the body of each file
+    #[must_use]
+    pub fn excerpt(self, excerpt: Option<Excerpt>) -> Self {
+        Self { excerpt, ..self }
+    }
+
+    /// Include the headings of each file
+    #[must_use]
+    pub fn outline(self, outline: bool) -> Self {
+        Self { outline, ..self }
+    }
+}
+
+fn inner_query(keeper: &Keeper, mut args: Args<'_>) -> Result<Response, CursorError> {
+    let files = if let Some(query) = args.query.take() {
+        query_files(keeper, query, None).collect::<Vec<_>>()
     } else {
-        files.cloned().map(Short::from).collect::<Vec<_>>()
+        keeper.files().collect::<Vec<_>>()
     };
 
     let total = files.len();
 
-    sort_with_params(args.sort_key, args.order_desc, &mut files);
+    let files = sort_with_params(&args.sort, args.order_desc, args.nulls_first, files);
 
-    let files = paginate(files, args.offset, args.limit);
+    let (start, end) = paginate(&files, &args)?;
 
-    Response { files, total }
+    let prev_cursor = (start > 0 && start < end).then(|| cursor::encode(&files[start].0));
+    let next_cursor = (end < files.len() && start < end).then(|| cursor::encode(&files[end - 1].0));
+
+    let files = files
+        .into_iter()
+        .skip(start)
+        .take(end - start)
+        .map(|(_, file)| Short::new(file.clone(), args.excerpt, args.outline))
+        .map(|file| match &args.fields {
+            Some(fields) => file.project(fields),
+            None => Projected::from(file),
+        })
+        .collect();
+
+    Ok(Response {
+        files,
+        total,
+        next_cursor,
+        prev_cursor,
+    })
 }
 
-#[must_use]
-pub fn query(keeper: &Keeper, args: Args<'_>) -> Response {
+pub fn query(keeper: &Keeper, args: Args<'_>) -> Result<Response, CursorError> {
     debug!("Received query request: {args:?}");
     let response = inner_query(keeper, args);
     debug!("Sending query response: {response:?}");
     response
 }
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use camino::Utf8PathBuf;
+    use chrono::TimeZone;
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        frontmatter_file::{FrontmatterFile, Keeper, Short},
+        sort::SortSpec,
+    };
+
+    fn file(name: &str, hour: u32) -> (Utf8PathBuf, FrontmatterFile) {
+        let dt = chrono::Utc
+            .with_ymd_and_hms(2024, 1, 1, hour, 0, 0)
+            .unwrap();
+        (
+            Utf8PathBuf::from(format!("/{name}")),
+            FrontmatterFile::new(name.to_owned(), None, String::new(), dt, dt),
+        )
+    }
+
+    fn names(response: &super::Response) -> Vec<&str> {
+        response
+            .files
+            .iter()
+            .filter_map(|f| f.name.as_deref())
+            .collect()
+    }
+
+    #[test]
+    fn cursors() {
+        let mut keeper = Keeper::from_files(
+            Utf8PathBuf::from("/"),
+            HashMap::from([
+                file("a.md", 1),
+                file("b.md", 2),
+                file("c.md", 3),
+                file("d.md", 4),
+            ]),
+        );
+
+        let first_page = super::query(
+            &keeper,
+            super::Args::get(vec![], false, false, None, Some(2)),
+        )
+        .unwrap();
+        assert_eq!(vec!["a.md", "b.md"], names(&first_page));
+        assert_eq!(None, first_page.prev_cursor);
+        let next_cursor = first_page.next_cursor.unwrap();
+
+        // A file added at the start would shift an offset, but not a cursor
+        let (path, new_file) = file("new.md", 0);
+        keeper.insert(path, new_file);
+
+        let second_page = super::query(
+            &keeper,
+            super::Args::get(vec![], false, false, None, Some(2)).cursors(Some(&next_cursor), None),
+        )
+        .unwrap();
+        assert_eq!(vec!["c.md", "d.md"], names(&second_page));
+        assert_eq!(5, second_page.total);
+        assert_eq!(None, second_page.next_cursor);
+        let prev_cursor = second_page.prev_cursor.unwrap();
+
+        let back_page = super::query(
+            &keeper,
+            super::Args::get(vec![], false, false, None, Some(2)).cursors(None, Some(&prev_cursor)),
+        )
+        .unwrap();
+        assert_eq!(vec!["a.md", "b.md"], names(&back_page));
+        assert!(back_page.prev_cursor.is_some());
+
+        let mismatched = super::query(
+            &keeper,
+            super::Args::get(
+                vec![SortSpec::new("a"), SortSpec::new("b")],
+                false,
+                false,
+                None,
+                None,
+            )
+            .cursors(Some(&next_cursor), None),
+        );
+        assert!(matches!(mismatched, Err(super::CursorError::SortMismatch)));
+    }
+
+    #[test]
+    fn fields() {
+        let (path, post) = file("post.md", 1);
+        let post = FrontmatterFile::new(
+            post.name().to_owned(),
+            Some(
+                serde_yaml::from_str("title: Post\nauthor:\n  handle: someone\ntags: [a, b]")
+                    .unwrap(),
+            ),
+            post.body().to_owned(),
+            *post.modified(),
+            *post.created(),
+        );
+        let keeper = Keeper::from_files(Utf8PathBuf::from("/"), HashMap::from([(path, post)]));
+
+        let response = super::query(
+            &keeper,
+            super::Args::get(vec![], false, false, None, None).fields(Some(vec![
+                "name",
+                "title",
+                "author.handle",
+                "missing",
+            ])),
+        )
+        .unwrap();
+        let projected = &response.files[0];
+        assert_eq!(Some("post.md"), projected.name.as_deref());
+        assert_eq!(None, projected.created);
+        assert_eq!(
+            Some(Some(
+                serde_yaml::from_str("title: Post\nauthor.handle: someone").unwrap()
+            )),
+            projected.frontmatter
+        );
+
+        let response = super::query(
+            &keeper,
+            super::Args::get(vec![], false, false, None, None).fields(Some(vec!["created"])),
+        )
+        .unwrap();
+        let projected = &response.files[0];
+        assert_eq!(None, projected.name);
+        assert_eq!(None, projected.frontmatter);
+        assert!(projected.created.is_some());
+        let keys = serde_json::to_value(projected)
+            .unwrap()
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["created"], keys, "fields left out aren't serialized");
+
+        let response =
+            super::query(&keeper, super::Args::get(vec![], false, false, None, None)).unwrap();
+        assert_eq!(
+            serde_json::to_string(&Short::from(keeper.files().next().unwrap().clone())).unwrap(),
+            serde_json::to_string(&response.files[0]).unwrap(),
+            "without fields, files keep the shape of a Short"
+        );
+    }
+}
@@ -0,0 +1,48 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+
+use crate::sort::Position;
+
+#[derive(Debug, thiserror::Error)]
+pub enum CursorError {
+    #[error("Cursor is not valid base64: {0}")]
+    Base64(#[from] base64::DecodeError),
+    #[error("Cursor is malformed: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Cursor was made for a different sort order")]
+    SortMismatch,
+}
+
+/// Cursors are opaque to clients, but are really the sort position of a file
+pub(super) fn encode(position: &Position) -> String {
+    URL_SAFE_NO_PAD.encode(serde_json::to_vec(position).expect("Position must serialize"))
+}
+
+pub(super) fn decode(cursor: &str, value_count: usize) -> Result<Position, CursorError> {
+    let position: Position = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(cursor)?)?;
+    if position.values.len() != value_count {
+        return Err(CursorError::SortMismatch);
+    }
+    Ok(position)
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use crate::sort::{Position, SortValue};
+
+    #[test]
+    fn numbers_round_trip() {
+        for number in (1..10_000).map(|i| f64::from(i) * 0.1 + 1e-9 / f64::from(i)) {
+            let position = Position {
+                values: vec![Some(SortValue::Number(number))],
+                name: "a.md".to_owned(),
+            };
+            assert_eq!(
+                position,
+                super::decode(&super::encode(&position), 1).unwrap(),
+                "cursors must resume at exactly the same number"
+            );
+        }
+    }
+}
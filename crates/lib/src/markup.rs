@@ -1,6 +1,45 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use pulldown_cmark::{Options, Parser};
 use serde::{de::DeserializeOwned, Serialize};
 
 pub fn yaml_to_json<T: Serialize, U: DeserializeOwned>(yaml: T) -> U {
     serde_json::from_value(serde_json::to_value(yaml).expect("valid yaml must map to valid json"))
         .expect("Map<String, Value> is valid json")
 }
+
+/// Interprets a string as a YAML-style timestamp, e.g. `2024-01-01` or `2024-01-01T10:30:00Z`.
+///
+/// Dates without a time are taken to be midnight UTC.
+pub fn parse_timestamp(s: &str) -> Option<DateTime<Utc>> {
+    let s = s.trim();
+    if let Ok(dt) = DateTime::parse_from_rfc3339(s) {
+        return Some(dt.with_timezone(&Utc));
+    }
+    for format in ["%Y-%m-%d %H:%M:%S%.f %z", "%Y-%m-%d %H:%M:%S%.f%z"] {
+        if let Ok(dt) = DateTime::parse_from_str(s, format) {
+            return Some(dt.with_timezone(&Utc));
+        }
+    }
+    for format in ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"] {
+        if let Ok(dt) = NaiveDateTime::parse_from_str(s, format) {
+            return Some(dt.and_utc());
+        }
+    }
+    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map(|dt| dt.and_utc())
+}
+
+/// Parses markdown with the extensions that custard supports, so that every consumer of a body
+/// sees the same structure
+pub fn markdown_parser(markdown: &str) -> Parser<'_> {
+    Parser::new_ext(
+        markdown,
+        Options::ENABLE_TABLES
+            | Options::ENABLE_FOOTNOTES
+            | Options::ENABLE_STRIKETHROUGH
+            | Options::ENABLE_TASKLISTS
+            | Options::ENABLE_WIKILINKS,
+    )
+}
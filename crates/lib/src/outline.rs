@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+
+use pulldown_cmark::{Event, Tag, TagEnd};
+use serde::Serialize;
+
+use crate::markup;
+
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct Heading {
+    pub level: u8,
+    pub text: String,
+    /// An anchor for the heading, unique within its file
+    pub slug: String,
+}
+
+/// Lowercases `text`, keeping only alphanumerics, `-` and `_`, and joining words with `-`
+fn slugify(text: &str) -> String {
+    text.split_whitespace()
+        .map(|word| {
+            word.chars()
+                .filter(|c| c.is_alphanumeric() || *c == '-' || *c == '_')
+                .flat_map(char::to_lowercase)
+                .collect::<String>()
+        })
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Every heading in `markdown`, in order. Repeated slugs get a `-1`, `-2`, ... suffix.
+#[must_use]
+pub fn outline(markdown: &str) -> Vec<Heading> {
+    let mut headings = Vec::new();
+    let mut seen = HashMap::<String, usize>::new();
+    let mut current: Option<(u8, String)> = None;
+
+    for event in markup::markdown_parser(markdown) {
+        match event {
+            Event::Start(Tag::Heading { level, .. }) => {
+                #[allow(clippy::cast_possible_truncation)]
+                let level = level as u8;
+                current = Some((level, String::new()));
+            }
+            Event::Text(t) | Event::Code(t) => {
+                if let Some((_, text)) = &mut current {
+                    text.push_str(&t);
+                }
+            }
+            Event::SoftBreak | Event::HardBreak => {
+                if let Some((_, text)) = &mut current {
+                    text.push(' ');
+                }
+            }
+            Event::End(TagEnd::Heading(_)) => {
+                let Some((level, text)) = current.take() else {
+                    continue;
+                };
+                let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+                let base = slugify(&text);
+                let count = seen.entry(base.clone()).or_default();
+                let slug = if *count == 0 {
+                    base
+                } else {
+                    format!("{base}-{count}")
+                };
+                *count += 1;
+                headings.push(Heading { level, text, slug });
+            }
+            _ => {}
+        }
+    }
+
+    headings
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::Heading;
+
+    fn heading(level: u8, text: &str, slug: &str) -> Heading {
+        Heading {
+            level,
+            text: text.to_owned(),
+            slug: slug.to_owned(),
+        }
+    }
+
+    #[test]
+    fn outline() {
+        let body = "# Getting *started*
+
+Intro.
+
+## Install `custard`
+
+```
+# not a heading
+```
+
+## Usage
+### Usage
+
+Setext
+------
+";
+        assert_eq!(
+            vec![
+                heading(1, "Getting started", "getting-started"),
+                heading(2, "Install custard", "install-custard"),
+                heading(2, "Usage", "usage"),
+                heading(3, "Usage", "usage-1"),
+                heading(2, "Setext", "setext"),
+            ],
+            super::outline(body)
+        );
+    }
+}
@@ -0,0 +1,68 @@
+use std::{
+    collections::HashMap,
+    sync::{Mutex, PoisonError},
+};
+
+use pulldown_cmark::{html, Event, Tag};
+
+use crate::{markup, outline};
+
+/// Headings are given `id`s from their [`outline::Heading::slug`], so the outline can link to them
+#[must_use]
+pub fn to_html(markdown: &str) -> String {
+    let mut slugs = outline::outline(markdown)
+        .into_iter()
+        .map(|heading| heading.slug);
+    let parser = markup::markdown_parser(markdown).map(|event| match event {
+        Event::Start(Tag::Heading {
+            level,
+            id: None,
+            classes,
+            attrs,
+        }) => Event::Start(Tag::Heading {
+            level,
+            id: slugs.next().map(Into::into),
+            classes,
+            attrs,
+        }),
+        event => event,
+    });
+    let mut html = String::with_capacity(markdown.len() * 3 / 2);
+    html::push_html(&mut html, parser);
+    html
+}
+
+/// Rendered HTML by file name. Files are only rendered once they are asked for, so this is
+/// filled in behind a shared reference to [`crate::frontmatter_file::Keeper`].
+#[derive(Debug, Default)]
+pub(crate) struct Cache(Mutex<HashMap<String, String>>);
+
+impl Cache {
+    /// Renders outside the lock, so that requests for different files don't wait on each other.
+    /// Two requests for the same file may both render it, and the first to finish is kept.
+    pub(crate) fn get_or_render(&self, name: &str, markdown: &str) -> String {
+        let cached = self
+            .0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(name)
+            .cloned();
+        if let Some(html) = cached {
+            return html;
+        }
+        let html = to_html(markdown);
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(name.to_owned())
+            .or_insert(html)
+            .clone()
+    }
+
+    pub(crate) fn invalidate(&mut self, name: &str) {
+        self.0
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(name);
+    }
+}
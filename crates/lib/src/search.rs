@@ -0,0 +1,340 @@
+use std::collections::{HashMap, HashSet};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::frontmatter_file::{FrontmatterFile, Keeper, Short};
+use crate::frontmatter_query::FrontmatterQuery;
+
+// BM25 tuning parameters
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+
+const SNIPPET_CHARS_BEFORE: usize = 60;
+const SNIPPET_CHARS_AFTER: usize = 100;
+
+/// Splits text into alphanumeric words, along with the byte offset each begins at
+fn words(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    let mut start = None;
+    text.char_indices()
+        .chain(std::iter::once((text.len(), ' ')))
+        .filter_map(move |(i, c)| match (start, c.is_alphanumeric()) {
+            (None, true) => {
+                start = Some(i);
+                None
+            }
+            (Some(s), false) => {
+                start = None;
+                Some((s, &text[s..i]))
+            }
+            _ => None,
+        })
+}
+
+fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    words(text).map(|(_, word)| word.to_lowercase())
+}
+
+/// An inverted index over file bodies, kept up to date by [`Keeper`]
+#[derive(Debug, Default)]
+pub struct Index {
+    postings: HashMap<String, HashSet<Utf8PathBuf>>,
+    term_frequencies: HashMap<Utf8PathBuf, HashMap<String, usize>>,
+    lengths: HashMap<Utf8PathBuf, usize>,
+    total_length: usize,
+}
+
+impl Index {
+    pub(crate) fn insert(&mut self, path: &Utf8Path, body: &str) {
+        self.remove(path);
+
+        let mut term_frequencies = HashMap::<String, usize>::new();
+        let mut length = 0;
+        for term in tokenize(body) {
+            *term_frequencies.entry(term).or_default() += 1;
+            length += 1;
+        }
+
+        for term in term_frequencies.keys() {
+            self.postings
+                .entry(term.clone())
+                .or_default()
+                .insert(path.to_owned());
+        }
+        self.term_frequencies
+            .insert(path.to_owned(), term_frequencies);
+        self.lengths.insert(path.to_owned(), length);
+        self.total_length += length;
+    }
+
+    pub(crate) fn remove(&mut self, path: &Utf8Path) {
+        let Some(term_frequencies) = self.term_frequencies.remove(path) else {
+            return;
+        };
+        for term in term_frequencies.keys() {
+            if let Some(paths) = self.postings.get_mut(term) {
+                paths.remove(path);
+                if paths.is_empty() {
+                    self.postings.remove(term);
+                }
+            }
+        }
+        if let Some(length) = self.lengths.remove(path) {
+            self.total_length -= length;
+        }
+    }
+
+    /// Scores every file containing all of `terms` using BM25
+    #[allow(clippy::cast_precision_loss)]
+    fn search(&self, terms: &[String]) -> HashMap<&Utf8PathBuf, f64> {
+        let mut scores = HashMap::new();
+        let Some((first, rest)) = terms.split_first() else {
+            return scores;
+        };
+        let Some(candidates) = self.postings.get(first) else {
+            return scores;
+        };
+
+        let document_count = self.lengths.len() as f64;
+        let average_length = self.total_length as f64 / document_count.max(1.0);
+
+        for path in candidates {
+            if !rest
+                .iter()
+                .all(|term| self.postings.get(term).is_some_and(|p| p.contains(path)))
+            {
+                continue;
+            }
+            let term_frequencies = &self.term_frequencies[path];
+            let length = self.lengths[path] as f64;
+            let score = terms
+                .iter()
+                .map(|term| {
+                    let containing = self.postings[term].len() as f64;
+                    let idf = ((document_count - containing + 0.5) / (containing + 0.5) + 1.0).ln();
+                    let frequency = term_frequencies[term] as f64;
+                    idf * (frequency * (K1 + 1.0))
+                        / (frequency + K1 * (1.0 - B + B * length / average_length))
+                })
+                .sum();
+            scores.insert(path, score);
+        }
+
+        scores
+    }
+}
+
+/// Some text surrounding the first occurrence of any of `terms` in `body`
+fn snippet(body: &str, terms: &[String]) -> String {
+    let Some(start) = words(body)
+        .find(|(_, word)| terms.contains(&word.to_lowercase()))
+        .map(|(offset, _)| offset)
+    else {
+        return String::new();
+    };
+
+    let before = body[..start]
+        .char_indices()
+        .rev()
+        .nth(SNIPPET_CHARS_BEFORE - 1)
+        .map_or(0, |(i, _)| i);
+    let after = body[start..]
+        .char_indices()
+        .nth(SNIPPET_CHARS_AFTER)
+        .map_or(body.len(), |(i, _)| start + i);
+
+    let mut snippet = body[before..after].split_whitespace().collect::<Vec<_>>();
+    // Drop words that were cut in half by the window
+    if before > 0 && !body[..before].ends_with(char::is_whitespace) && snippet.len() > 1 {
+        snippet.remove(0);
+    }
+    if after < body.len() && !body[after..].starts_with(char::is_whitespace) && snippet.len() > 1 {
+        snippet.pop();
+    }
+
+    let mut snippet = snippet.join(" ");
+    if before > 0 {
+        snippet.insert_str(0, "… ");
+    }
+    if after < body.len() {
+        snippet.push_str(" …");
+    }
+    snippet
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Args<'a> {
+    pub text: &'a str,
+    #[serde(default)]
+    pub query: Option<FrontmatterQuery>,
+    #[serde(default)]
+    pub offset: Option<usize>,
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Hit {
+    pub file: Short,
+    pub score: f64,
+    pub snippet: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Response {
+    pub hits: Vec<Hit>,
+    pub total: usize,
+}
+
+#[allow(clippy::needless_pass_by_value)]
+fn inner(keeper: &Keeper, args: Args<'_>) -> Response {
+    let terms = tokenize(args.text).collect::<Vec<_>>();
+
+    let mut hits = keeper
+        .search_index()
+        .search(&terms)
+        .into_iter()
+        .filter_map(|(path, score)| {
+            let file = keeper.get(path)?;
+            let matches_query = args
+                .query
+                .as_ref()
+                .is_none_or(|query| query.matches(file.json_frontmatter()));
+            matches_query.then_some((file, score))
+        })
+        .collect::<Vec<(&FrontmatterFile, f64)>>();
+
+    hits.sort_by(|(f, f_score), (g, g_score)| {
+        g_score
+            .total_cmp(f_score)
+            .then_with(|| f.name().cmp(g.name()))
+    });
+
+    let total = hits.len();
+
+    let hits = hits
+        .into_iter()
+        .skip(args.offset.unwrap_or_default())
+        .take(args.limit.unwrap_or(usize::MAX))
+        .map(|(file, score)| Hit {
+            snippet: snippet(file.body(), &terms),
+            file: Short::from(file.clone()),
+            score,
+        })
+        .collect();
+
+    Response { hits, total }
+}
+
+#[must_use]
+pub fn search(keeper: &Keeper, args: Args<'_>) -> Response {
+    debug!("Received search request: {args:?}");
+    let response = inner(keeper, args);
+    debug!("Sending search response: {response:?}");
+    response
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use camino::Utf8PathBuf;
+    use chrono::TimeZone;
+    use pretty_assertions::assert_eq;
+
+    use crate::frontmatter_file::{FrontmatterFile, Keeper};
+
+    fn file(name: &str, body: &str) -> (Utf8PathBuf, FrontmatterFile) {
+        let dt = chrono::Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        (
+            Utf8PathBuf::from(format!("/{name}")),
+            FrontmatterFile::new(name.to_owned(), None, body.to_owned(), dt, dt),
+        )
+    }
+
+    fn make_test_keeper() -> Keeper {
+        Keeper::from_files(
+            Utf8PathBuf::from("/"),
+            HashMap::from([
+                file(
+                    "tokyo.md",
+                    "Arrived in Tokyo. Tokyo is huge, and the trains are fast.",
+                ),
+                file(
+                    "kyoto.md",
+                    "Took the fast train from Tokyo to Kyoto to see the temples.",
+                ),
+                file("cheese.md", "A recipe for chai cheese."),
+            ]),
+        )
+    }
+
+    #[test]
+    fn ranked() {
+        let keeper = make_test_keeper();
+
+        let response = super::search(
+            &keeper,
+            super::Args {
+                text: "tokyo",
+                query: None,
+                offset: None,
+                limit: None,
+            },
+        );
+        let names = response
+            .hits
+            .iter()
+            .map(|hit| hit.file.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["tokyo.md", "kyoto.md"], names);
+        assert_eq!(2, response.total);
+
+        let response = super::search(
+            &keeper,
+            super::Args {
+                text: "Fast temples",
+                query: None,
+                offset: None,
+                limit: None,
+            },
+        );
+        let names = response
+            .hits
+            .iter()
+            .map(|hit| hit.file.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["kyoto.md"], names, "every term must be present");
+    }
+
+    #[test]
+    fn index_updates() {
+        let mut keeper = make_test_keeper();
+        let (path, cheese) = file("cheese.md", "Chai cheese, best eaten in Tokyo.");
+        keeper.insert(path.clone(), cheese);
+
+        let args = || super::Args {
+            text: "chai",
+            query: None,
+            offset: None,
+            limit: None,
+        };
+        assert_eq!(1, super::search(&keeper, args()).total);
+
+        keeper.remove(&path);
+        assert_eq!(0, super::search(&keeper, args()).total);
+    }
+
+    #[test]
+    fn snippet() {
+        let body = "word ".repeat(40) + "needle " + &"word ".repeat(40);
+        let snippet = super::snippet(&body, &["needle".to_owned()]);
+        assert!(snippet.starts_with("… word"));
+        assert!(snippet.contains(" needle "));
+        assert!(snippet.ends_with("word …"));
+
+        let snippet = super::snippet("A short needle.", &["needle".to_owned()]);
+        assert_eq!("A short needle.", snippet);
+    }
+}
@@ -3,21 +3,35 @@ use tracing::debug;
 
 use crate::frontmatter_file::{FrontmatterFile, Keeper};
 use crate::frontmatter_query::FrontmatterQuery;
-use crate::{get_sort_value, query_files};
-
-fn sort_with_params(sort_key: Option<&str>, order_desc: bool, files: &mut [&FrontmatterFile]) {
-    if let Some(sort_key) = sort_key {
-        files.sort_by(|f, g| {
-            let f_value = get_sort_value(f.frontmatter(), &f.created, sort_key);
-            let g_value = get_sort_value(g.frontmatter(), &g.created, sort_key);
-            f_value.cmp(&g_value)
-        });
-    } else {
-        files.sort();
-    }
+use crate::links::{self, Link};
+use crate::sort::{self, SortSpec};
+use crate::{get_sort_position, query_files};
+
+fn sort_with_params(
+    sort: &[SortSpec<'_>],
+    order_desc: bool,
+    nulls_first: bool,
+    files: &mut [&FrontmatterFile],
+) {
+    let mut positioned = files
+        .iter()
+        .map(|file| {
+            let position = get_sort_position(
+                file.json_frontmatter(),
+                file.name(),
+                file.created(),
+                file.modified(),
+                sort,
+            );
+            (position, *file)
+        })
+        .collect::<Vec<_>>();
+
+    positioned
+        .sort_by(|(a, _), (b, _)| sort::compare_positions(a, b, sort, order_desc, nulls_first));
 
-    if order_desc {
-        files.reverse();
+    for (slot, (_, file)) in files.iter_mut().zip(positioned) {
+        *slot = file;
     }
 }
 
@@ -50,6 +64,12 @@ pub struct Response<'a> {
     pub file: &'a FrontmatterFile,
     pub prev_file_name: Option<&'a str>,
     pub next_file_name: Option<&'a str>,
+    /// The file's body rendered as HTML, if it was asked for
+    pub html: Option<String>,
+    /// The links in the file, if they were asked for
+    pub links: Option<Vec<Link<'a>>>,
+    /// The names of files that link to this one, if links were asked for
+    pub backlinks: Option<Vec<&'a str>>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -57,32 +77,53 @@ pub struct Args<'a> {
     pub name: &'a str,
     #[serde(default)]
     pub query: Option<FrontmatterQuery>,
-    #[serde(default)]
-    pub sort_key: Option<&'a str>,
+    #[serde(default, borrow)]
+    pub sort: Vec<SortSpec<'a>>,
     #[serde(default)]
     pub order_desc: bool,
+    #[serde(default)]
+    pub nulls_first: bool,
+    /// Also render the file's body as HTML. Needs the `html` feature.
+    #[serde(default)]
+    pub render_html: bool,
+    /// Also include the file's links and backlinks
+    #[serde(default)]
+    pub links: bool,
 }
 
 #[allow(clippy::needless_pass_by_value)]
 fn inner<'a, 'b: 'a>(keeper: &'a Keeper, args: Args<'b>) -> Option<Response<'a>> {
-    let files = keeper.files();
-
     let mut files = if let Some(query) = args.query {
-        query_files(files, query, Some(args.name)).collect::<Vec<_>>()
+        query_files(keeper, query, Some(args.name)).collect::<Vec<_>>()
     } else {
-        files.collect::<Vec<_>>()
+        keeper.files().collect::<Vec<_>>()
     };
 
-    sort_with_params(args.sort_key, args.order_desc, &mut files);
+    sort_with_params(&args.sort, args.order_desc, args.nulls_first, &mut files);
 
     let (i, file) = find_file_and_index(&files, args.name)?;
 
     let (prev_file_name, next_file_name) = get_prev_and_next_file_names(&files, i);
 
+    #[cfg(feature = "html")]
+    let html = args.render_html.then(|| keeper.html(file));
+    #[cfg(not(feature = "html"))]
+    let html = None;
+
+    let (links, backlinks) = if args.links {
+        let (links, backlinks) = links::for_file(keeper, file.name());
+        (Some(links), Some(backlinks))
+    } else {
+        (None, None)
+    };
+
     Some(Response {
         file,
         prev_file_name,
         next_file_name,
+        html,
+        links,
+        backlinks,
     })
 }
 
@@ -107,6 +148,7 @@ mod test {
     use crate::{
         frontmatter_file::{FrontmatterFile, Keeper},
         frontmatter_query::{FrontmatterQuery, FrontmatterQueryMap, QueryValue, Scalar},
+        sort::SortSpec,
     };
 
     macro_rules! s {
@@ -150,35 +192,35 @@ mod test {
         );
         hm.insert(
             path!("/something.md"),
-            FrontmatterFile {
-                name: s!("something.md"),
-                frontmatter: None,
-                body: s!(""),
-                modified: dt!(2024, 1, 1, 6),
-                created: dt!(2024, 1, 1, 5),
-            },
+            FrontmatterFile::new(
+                s!("something.md"),
+                None,
+                s!(""),
+                dt!(2024, 1, 1, 6),
+                dt!(2024, 1, 1, 5),
+            ),
         );
         hm.insert(
             path!("/about.md"),
-            FrontmatterFile {
-                name: s!("about.md"),
-                frontmatter: Some(fm.clone()),
-                body: s!(""),
-                modified: dt!(2024, 1, 1, 11),
-                created: dt!(2024, 1, 1, 9),
-            },
+            FrontmatterFile::new(
+                s!("about.md"),
+                Some(fm.clone()),
+                s!(""),
+                dt!(2024, 1, 1, 11),
+                dt!(2024, 1, 1, 9),
+            ),
         );
         hm.insert(
             path!("/blah.md"),
-            FrontmatterFile {
-                name: s!("blah.md"),
-                frontmatter: Some(fm),
-                body: s!(""),
-                modified: dt!(2024, 1, 1, 16),
-                created: dt!(2024, 1, 1, 15),
-            },
+            FrontmatterFile::new(
+                s!("blah.md"),
+                Some(fm),
+                s!(""),
+                dt!(2024, 1, 1, 16),
+                dt!(2024, 1, 1, 15),
+            ),
         );
-        Keeper { inner: hm }
+        Keeper::from_files(path!("/"), hm)
     }
 
     #[test]
@@ -190,8 +232,11 @@ mod test {
             super::Args {
                 name: "something.md",
                 query: None,
-                sort_key: Some("created"),
+                sort: vec![SortSpec::new("created")],
                 order_desc: true,
+                nulls_first: false,
+                render_html: false,
+                links: false,
             },
         )
         .unwrap();
@@ -203,8 +248,11 @@ mod test {
             super::Args {
                 name: "about.md",
                 query: None,
-                sort_key: Some("created"),
+                sort: vec![SortSpec::new("created")],
                 order_desc: true,
+                nulls_first: false,
+                render_html: false,
+                links: false,
             },
         )
         .unwrap();
@@ -216,8 +264,11 @@ mod test {
             super::Args {
                 name: "blah.md",
                 query: None,
-                sort_key: Some("created"),
+                sort: vec![SortSpec::new("created")],
                 order_desc: true,
+                nulls_first: false,
+                render_html: false,
+                links: false,
             },
         )
         .unwrap();
@@ -237,12 +288,12 @@ mod test {
             &keeper,
             super::Args {
                 name: "about.md",
-                query: Some(FrontmatterQuery {
-                    map: query_map.clone(),
-                    intersect: false,
-                }),
-                sort_key: Some("created"),
+                query: Some(FrontmatterQuery::Subset(query_map.clone())),
+                sort: vec![SortSpec::new("created")],
                 order_desc: true,
+                nulls_first: false,
+                render_html: false,
+                links: false,
             },
         )
         .unwrap();
@@ -253,12 +304,12 @@ mod test {
             &keeper,
             super::Args {
                 name: "blah.md",
-                query: Some(FrontmatterQuery {
-                    map: query_map.clone(),
-                    intersect: false,
-                }),
-                sort_key: Some("created"),
+                query: Some(FrontmatterQuery::Subset(query_map.clone())),
+                sort: vec![SortSpec::new("created")],
                 order_desc: true,
+                nulls_first: false,
+                render_html: false,
+                links: false,
             },
         )
         .unwrap();
@@ -269,16 +320,81 @@ mod test {
             &keeper,
             super::Args {
                 name: "something.md",
-                query: Some(FrontmatterQuery {
-                    map: query_map,
-                    intersect: false,
-                }),
-                sort_key: Some("created"),
+                query: Some(FrontmatterQuery::Subset(query_map)),
+                sort: vec![SortSpec::new("created")],
                 order_desc: true,
+                nulls_first: false,
+                render_html: false,
+                links: false,
             },
         )
         .unwrap();
         assert_eq!(None, response.next_file_name);
         assert_eq!(Some("about.md"), response.prev_file_name);
     }
+
+    #[test]
+    fn multi_sort() {
+        let keeper = make_test_keeper();
+
+        // Tags ascending, then created descending, with the untagged file last
+        let sort = vec![
+            SortSpec {
+                key: "tag",
+                desc: Some(false),
+            },
+            SortSpec::new("created"),
+        ];
+
+        let response = super::single(
+            &keeper,
+            super::Args {
+                name: "about.md",
+                query: None,
+                sort,
+                order_desc: true,
+                nulls_first: false,
+                render_html: false,
+                links: false,
+            },
+        )
+        .unwrap();
+        assert_eq!(Some("blah.md"), response.prev_file_name);
+        assert_eq!(Some("something.md"), response.next_file_name);
+    }
+
+    #[cfg(feature = "html")]
+    #[test]
+    fn render_html() {
+        let mut keeper = make_test_keeper();
+        let args = || super::Args {
+            name: "about.md",
+            query: None,
+            sort: vec![],
+            order_desc: true,
+            nulls_first: false,
+            render_html: true,
+            links: false,
+        };
+
+        let response = super::single(&keeper, args()).unwrap();
+        assert_eq!(Some(s!("")), response.html);
+
+        let file = response.file;
+        let edited = FrontmatterFile::new(
+            file.name().to_owned(),
+            file.frontmatter().cloned(),
+            s!("Some *emphasis*"),
+            *file.modified(),
+            *file.created(),
+        );
+        keeper.insert(path!("/about.md"), edited);
+
+        let response = super::single(&keeper, args()).unwrap();
+        assert_eq!(
+            Some(s!("<p>Some <em>emphasis</em></p>\n")),
+            response.html,
+            "edits must invalidate the cached render"
+        );
+    }
 }
@@ -0,0 +1,246 @@
+use std::cmp::Ordering;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::markup::parse_timestamp;
+
+/// One key to sort by. Where `desc` isn't given, the request's overall direction is used.
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+pub struct SortSpec<'a> {
+    pub key: &'a str,
+    #[serde(default)]
+    pub desc: Option<bool>,
+}
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+#[error("Unrecognised sort direction: '{0}'")]
+pub struct ParseSortSpecError(String);
+
+impl<'a> SortSpec<'a> {
+    #[must_use]
+    pub fn new(key: &'a str) -> Self {
+        Self { key, desc: None }
+    }
+
+    /// Parses a comma-separated list of keys, each optionally suffixed with `:asc` or `:desc`,
+    /// e.g. `pinned:desc,date:desc,title`
+    pub fn parse_list(s: &'a str) -> Result<Vec<Self>, ParseSortSpecError> {
+        s.split(',')
+            .filter(|spec| !spec.is_empty())
+            .map(|spec| {
+                let Some((key, direction)) = spec.rsplit_once(':') else {
+                    return Ok(Self::new(spec));
+                };
+                let desc = match direction {
+                    "asc" => false,
+                    "desc" => true,
+                    unknown => return Err(ParseSortSpecError(unknown.to_owned())),
+                };
+                Ok(Self {
+                    key,
+                    desc: Some(desc),
+                })
+            })
+            .collect()
+    }
+}
+
+/// A frontmatter value as it should be ordered when sorting.
+///
+/// Values of the same type compare naturally: numbers numerically, timestamps chronologically and
+/// strings lexically. Differing types are ordered booleans, then numbers, then timestamps, then
+/// strings, then anything else (sequences and mappings, compared as YAML).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) enum SortValue {
+    Bool(bool),
+    Number(f64),
+    Timestamp(DateTime<Utc>),
+    String(String),
+    Other(String),
+}
+
+impl SortValue {
+    /// `None` for nulls, which are ordered separately
+    pub(crate) fn from_json(value: &serde_json::Value) -> Option<Self> {
+        match value {
+            serde_json::Value::Null => None,
+            serde_json::Value::Bool(b) => Some(Self::Bool(*b)),
+            serde_json::Value::Number(n) => n.as_f64().map(Self::Number),
+            serde_json::Value::String(s) => {
+                Some(parse_timestamp(s).map_or_else(|| Self::String(s.clone()), Self::Timestamp))
+            }
+            other => Some(Self::Other(other.to_string())),
+        }
+    }
+
+    fn type_rank(&self) -> u8 {
+        match self {
+            Self::Bool(_) => 0,
+            Self::Number(_) => 1,
+            Self::Timestamp(_) => 2,
+            Self::String(_) => 3,
+            Self::Other(_) => 4,
+        }
+    }
+}
+
+impl Eq for SortValue {}
+
+impl PartialOrd for SortValue {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for SortValue {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Self::Bool(a), Self::Bool(b)) => a.cmp(b),
+            (Self::Number(a), Self::Number(b)) => a.total_cmp(b),
+            (Self::Timestamp(a), Self::Timestamp(b)) => a.cmp(b),
+            (Self::String(a), Self::String(b)) | (Self::Other(a), Self::Other(b)) => a.cmp(b),
+            (a, b) => a.type_rank().cmp(&b.type_rank()),
+        }
+    }
+}
+
+/// Orders two sort values in the requested direction. Nulls and missing values are placed last
+/// regardless of direction, unless `nulls_first` is set.
+pub(crate) fn compare(
+    a: Option<&SortValue>,
+    b: Option<&SortValue>,
+    order_desc: bool,
+    nulls_first: bool,
+) -> Ordering {
+    match (a, b) {
+        (None, None) => Ordering::Equal,
+        (None, Some(_)) if nulls_first => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (Some(_), None) if nulls_first => Ordering::Greater,
+        (Some(_), None) => Ordering::Less,
+        (Some(a), Some(b)) if order_desc => b.cmp(a),
+        (Some(a), Some(b)) => a.cmp(b),
+    }
+}
+
+/// Where a file falls in a sort order: its value for each sort spec, or its created time if there
+/// are none, followed by its name to break ties.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Position {
+    pub values: Vec<Option<SortValue>>,
+    pub name: String,
+}
+
+/// The direction of each value in a [`Position`]
+pub(crate) fn directions<'a>(
+    specs: &'a [SortSpec<'_>],
+    order_desc: bool,
+) -> impl Iterator<Item = bool> + 'a {
+    let default = specs.is_empty().then_some(order_desc);
+    default.into_iter().chain(
+        specs
+            .iter()
+            .map(move |spec| spec.desc.unwrap_or(order_desc)),
+    )
+}
+
+/// Compares value by value until one tells the positions apart, falling back on names so that
+/// files with equal sort values always come out in the same order
+pub(crate) fn compare_positions(
+    a: &Position,
+    b: &Position,
+    specs: &[SortSpec<'_>],
+    order_desc: bool,
+    nulls_first: bool,
+) -> Ordering {
+    a.values
+        .iter()
+        .zip(&b.values)
+        .zip(directions(specs, order_desc))
+        .map(|((a, b), desc)| compare(a.as_ref(), b.as_ref(), desc, nulls_first))
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or(Ordering::Equal)
+        .then_with(|| a.name.cmp(&b.name))
+}
+
+#[cfg(test)]
+mod test {
+    use std::cmp::Ordering;
+
+    use super::{compare, ParseSortSpecError, SortSpec, SortValue};
+
+    fn value(yaml: &str) -> Option<SortValue> {
+        let yaml = serde_yaml::from_str::<serde_yaml::Value>(yaml).unwrap();
+        SortValue::from_json(&crate::markup::yaml_to_json(yaml))
+    }
+
+    #[test]
+    fn typed() {
+        assert!(value("9") < value("10"), "numbers compare numerically");
+        assert!(value("2.5") < value("10"));
+        assert!(value("false") < value("true"));
+        assert!(
+            value("2024-01-02") < value("2024-01-02T10:00:00Z"),
+            "dates compare chronologically"
+        );
+        assert!(value("apple") < value("banana"));
+    }
+
+    #[test]
+    fn mixed() {
+        assert!(value("true") < value("1"));
+        assert!(value("1") < value("2024-01-01"));
+        assert!(value("2024-01-01") < value("apple"));
+        assert!(value("apple") < value("[1, 2]"));
+    }
+
+    #[test]
+    fn nulls() {
+        let null = value("null");
+        let one = value("1");
+        let two = value("2");
+
+        assert_eq!(None, null);
+        assert_eq!(
+            Ordering::Greater,
+            compare(null.as_ref(), one.as_ref(), false, false)
+        );
+        assert_eq!(
+            Ordering::Greater,
+            compare(null.as_ref(), one.as_ref(), true, false),
+            "nulls are last in either direction"
+        );
+        assert_eq!(
+            Ordering::Less,
+            compare(null.as_ref(), one.as_ref(), true, true)
+        );
+        assert_eq!(
+            Ordering::Greater,
+            compare(one.as_ref(), two.as_ref(), true, false)
+        );
+    }
+
+    #[test]
+    fn parse_list() {
+        assert_eq!(
+            Ok(vec![
+                SortSpec {
+                    key: "pinned",
+                    desc: Some(true)
+                },
+                SortSpec {
+                    key: "date",
+                    desc: Some(false)
+                },
+                SortSpec::new("title"),
+            ]),
+            SortSpec::parse_list("pinned:desc,date:asc,title")
+        );
+
+        assert_eq!(
+            Err(ParseSortSpecError("up".to_owned())),
+            SortSpec::parse_list("date:up")
+        );
+    }
+}
@@ -1,17 +1,28 @@
 use anyhow::anyhow;
-use camino::Utf8PathBuf;
+use std::collections::HashMap;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use clap::Parser;
 use custard_lib::{
+    changes::{self, Change, Event},
     collate,
-    frontmatter_file::{self, Keeper},
-    list, single,
+    config::Config,
+    frontmatter_file::{
+        keeper::{ArcRwLock, LoadError, Options},
+        Keeper,
+    },
+    frontmatter_query::FrontmatterQuery,
+    links, list, search, single,
 };
-use notify::{RecursiveMode, Watcher};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
 use serde::{Deserialize, Serialize};
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
-    net::UnixListener,
+    net::{UnixListener, UnixStream},
+    sync::broadcast::{self, error::RecvError},
 };
 use tracing::{debug, error, info};
+use tracing_subscriber::filter::LevelFilter;
 
 #[derive(Serialize, Debug)]
 #[serde(tag = "tag", content = "value")]
@@ -20,6 +31,7 @@ enum Result<T: Serialize> {
     #[allow(dead_code)]
     // unit value is needed because msgpack will panic if `value` is not present
     InternalServerError(()),
+    BadRequest(String),
 }
 
 static INTERNAL_SERVER_ERROR_BYTES: [u8; 22] = [
@@ -32,7 +44,15 @@ static INTERNAL_SERVER_ERROR_BYTES: [u8; 22] = [
 enum Response<'a> {
     Single(Option<single::Response<'a>>),
     List(list::Response),
+    /// A list with only some fields of each file, which has to be encoded with field names
+    Projected(list::Response),
     Collate(Vec<String>),
+    Search(search::Response),
+    Links(Option<links::Response<'a>>),
+    BrokenLinks(Vec<links::BrokenLink>),
+    Errors(Vec<LoadError>),
+    #[serde(skip)]
+    Subscribe(Subscription),
 }
 
 #[derive(Deserialize, Debug)]
@@ -42,72 +62,201 @@ enum Request<'a> {
     Single(single::Args<'a>),
     List(list::Args<'a>),
     Collate(collate::Args<'a>),
+    Search(search::Args<'a>),
+    Links(links::Args<'a>),
+    // unit value is needed because msgpack will panic if `value` is not present
+    BrokenLinks(()),
+    Errors(()),
+    Subscribe(changes::Args),
 }
 
 impl<'kep, 'req: 'kep> Request<'req> {
-    fn process(self, keeper: &'kep Keeper) -> Response<'kep> {
+    fn process(self, keeper: &'kep Keeper) -> Result<Response<'kep>> {
         match self {
             Request::Single(args) => {
                 let response = custard_lib::single::single(keeper, args);
-                Response::Single(response)
+                Result::Ok(Response::Single(response))
             }
             Request::List(args) => {
-                let response = custard_lib::list::query(keeper, args);
-                Response::List(response)
+                let is_projected = args.fields.is_some();
+                match custard_lib::list::query(keeper, args) {
+                    Ok(response) if is_projected => Result::Ok(Response::Projected(response)),
+                    Ok(response) => Result::Ok(Response::List(response)),
+                    Err(err) => Result::BadRequest(err.to_string()),
+                }
             }
             Request::Collate(args) => {
                 let response = custard_lib::collate::collate(keeper, args);
-                Response::Collate(response)
+                Result::Ok(Response::Collate(response))
+            }
+            Request::Search(args) => {
+                let response = custard_lib::search::search(keeper, args);
+                Result::Ok(Response::Search(response))
+            }
+            Request::Links(args) => {
+                let response = custard_lib::links::links(keeper, args);
+                Result::Ok(Response::Links(response))
+            }
+            Request::BrokenLinks(()) => {
+                let response = custard_lib::links::broken(keeper);
+                Result::Ok(Response::BrokenLinks(response))
+            }
+            Request::Errors(()) => {
+                let response = custard_lib::health::errors(keeper);
+                Result::Ok(Response::Errors(response))
+            }
+            Request::Subscribe(args) => Result::Ok(Response::Subscribe(Subscription {
+                changes: keeper.subscribe(),
+                query: args.query,
+            })),
+        }
+    }
+}
+
+/// Changes to a collection's files, for a client that keeps its stream open
+#[derive(Debug)]
+struct Subscription {
+    changes: broadcast::Receiver<Change>,
+    query: Option<FrontmatterQuery>,
+}
+
+impl Subscription {
+    /// Sends each change as an [`Event`], preceded by its length like a request is, until the
+    /// client hangs up
+    async fn send_changes(mut self, stream: &mut UnixStream) {
+        let (mut reader, mut writer) = stream.split();
+        loop {
+            let change = tokio::select! {
+                change = self.changes.recv() => change,
+                // Nothing more is expected from a subscriber, so this only ends when it hangs up
+                _ = reader.read_u8() => {
+                    debug!("subscriber hung up");
+                    return;
+                }
+            };
+            let event = match change {
+                Ok(change) => match change.into_event(self.query.as_ref()) {
+                    Some(event) => event,
+                    None => continue,
+                },
+                Err(RecvError::Lagged(missed)) => Event::Lagged(missed),
+                Err(RecvError::Closed) => return,
+            };
+            let out_buf = match rmp_serde::to_vec(&event) {
+                Ok(out_buf) => out_buf,
+                Err(err) => {
+                    error!("Failed to serialize event: {err}");
+                    continue;
+                }
+            };
+            let Ok(length) = u32::try_from(out_buf.len()) else {
+                error!("Event too large to send: {} bytes", out_buf.len());
+                continue;
+            };
+            debug!("Sending event bytes: {:x?}", out_buf);
+            let written = match writer.write_u32(length).await {
+                Ok(()) => writer.write_all(&out_buf).await,
+                Err(err) => Err(err),
+            };
+            if let Err(err) = written {
+                error!("subscription write failed: {err}");
+                return;
             }
         }
     }
 }
 
-fn in_buf_2_out_buf(markdown_files: &frontmatter_file::keeper::ArcMutex, in_buf: &[u8]) -> Vec<u8> {
+/// A [`Request`] and the collection it's for, which is sent alongside its `tag` and `value`
+#[derive(Deserialize, Debug)]
+struct Envelope<'a> {
+    #[serde(borrow, flatten)]
+    request: Request<'a>,
+    #[serde(borrow, default)]
+    collection: Option<&'a str>,
+}
+
+/// The files served, by collection. Requests without a `collection` get the default files.
+#[derive(Clone, Default)]
+struct Collections {
+    default: Option<ArcRwLock>,
+    named: HashMap<String, ArcRwLock>,
+}
+
+impl Collections {
+    fn get(&self, collection: Option<&str>) -> Option<&ArcRwLock> {
+        match collection {
+            Some(name) => self.named.get(name),
+            None => self.default.as_ref(),
+        }
+    }
+}
+
+/// The response to send, and the subscription to serve after it if one was asked for
+fn in_buf_2_out_buf(collections: &Collections, in_buf: &[u8]) -> (Vec<u8>, Option<Subscription>) {
     debug!("Received bytes: {in_buf:x?}");
-    let req = match rmp_serde::from_slice::<Request>(in_buf) {
+    let Envelope {
+        request: req,
+        collection,
+    } = match rmp_serde::from_slice::<Envelope>(in_buf) {
         Ok(req) => req,
         Err(err) => {
             error!("stream request decode failed: {err}");
-            return INTERNAL_SERVER_ERROR_BYTES.to_vec();
+            return (INTERNAL_SERVER_ERROR_BYTES.to_vec(), None);
         }
     };
 
-    let keeper = match markdown_files.lock() {
-        Ok(keeper) => keeper,
-        Err(err) => {
-            error!("Failed to lock markdown files: {err}");
-            return INTERNAL_SERVER_ERROR_BYTES.to_vec();
-        }
+    let Some(markdown_files) = collections.get(collection) else {
+        let message = match collection {
+            Some(name) => format!("Unknown collection: {name}"),
+            None => "A collection must be given".to_owned(),
+        };
+        error!("Bad request: {message}");
+        let out_buf = rmp_serde::to_vec(&Result::<()>::BadRequest(message))
+            .unwrap_or_else(|_| INTERNAL_SERVER_ERROR_BYTES.to_vec());
+        return (out_buf, None);
     };
+
+    let keeper = markdown_files.read();
     let resp = req.process(&keeper);
 
+    let mut subscription = None;
     let out_buf = match resp {
-        Response::Single(response) => rmp_serde::to_vec(&Result::Ok(response)),
-        Response::List(list) => rmp_serde::to_vec(&Result::Ok(list)),
-        Response::Collate(vec) => rmp_serde::to_vec(&Result::Ok(vec)),
+        Result::Ok(Response::Single(response)) => rmp_serde::to_vec(&Result::Ok(response)),
+        Result::Ok(Response::List(list)) => rmp_serde::to_vec(&Result::Ok(list)),
+        Result::Ok(Response::Projected(list)) => rmp_serde::to_vec_named(&Result::Ok(list)),
+        Result::Ok(Response::Collate(vec)) => rmp_serde::to_vec(&Result::Ok(vec)),
+        Result::Ok(Response::Search(search)) => rmp_serde::to_vec(&Result::Ok(search)),
+        Result::Ok(Response::Links(links)) => rmp_serde::to_vec(&Result::Ok(links)),
+        Result::Ok(Response::BrokenLinks(broken)) => rmp_serde::to_vec(&Result::Ok(broken)),
+        Result::Ok(Response::Errors(errors)) => rmp_serde::to_vec(&Result::Ok(errors)),
+        Result::Ok(Response::Subscribe(subscribed)) => {
+            subscription = Some(subscribed);
+            rmp_serde::to_vec(&Result::Ok(()))
+        }
+        Result::BadRequest(message) => {
+            error!("Bad request: {message}");
+            rmp_serde::to_vec(&Result::<()>::BadRequest(message))
+        }
+        Result::InternalServerError(()) => return (INTERNAL_SERVER_ERROR_BYTES.to_vec(), None),
     };
 
     match out_buf {
         Ok(out_buf) => {
             debug!("Sending bytes: {:x?}", out_buf);
-            out_buf
+            (out_buf, subscription)
         }
         Err(err) => {
             error!("Failed to serialize response: {err}");
-            INTERNAL_SERVER_ERROR_BYTES.to_vec()
+            (INTERNAL_SERVER_ERROR_BYTES.to_vec(), None)
         }
     }
 }
 
-async fn accept_streams(
-    markdown_files: frontmatter_file::keeper::ArcMutex,
-    listener: UnixListener,
-) {
+async fn accept_streams(collections: Collections, listener: UnixListener) {
     info!("listening for streams...");
     while let Ok((mut stream, _addr)) = listener.accept().await {
         debug!("accepted stream");
-        let mf = markdown_files.clone();
+        let collections = collections.clone();
         tokio::spawn(async move {
             let mut buf = Vec::new();
 
@@ -127,11 +276,14 @@ async fn accept_streams(
             match stream.read_exact(&mut buf).await {
                 Ok(n) => {
                     debug!("read {n} bytes");
-                    let out_buf = in_buf_2_out_buf(&mf, &buf[..n]);
+                    let (out_buf, subscription) = in_buf_2_out_buf(&collections, &buf[..n]);
                     if let Err(err) = stream.write_all(&out_buf).await {
                         error!("stream write failed: {err}");
                     } else {
                         debug!("successfully resolved request/response");
+                        if let Some(subscription) = subscription {
+                            subscription.send_changes(&mut stream).await;
+                        }
                     }
                 }
                 Err(err) => {
@@ -142,41 +294,90 @@ async fn accept_streams(
     }
 }
 
+fn watch(dir: &Utf8Path, options: Options) -> anyhow::Result<(ArcRwLock, RecommendedWatcher)> {
+    let keeper = Keeper::with_options(dir, options)?;
+
+    let markdown_files = ArcRwLock::new(keeper);
+
+    let mut watcher = notify::recommended_watcher(markdown_files.clone())?;
+
+    watcher.watch(dir.as_std_path(), RecursiveMode::Recursive)?;
+
+    Ok((markdown_files, watcher))
+}
+
+/// Serves the markdown files in a directory over a Unix socket
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    /// Path of the socket to create
+    #[arg(short, long)]
+    socket: Option<Utf8PathBuf>,
+    /// Directory to serve [default: the current directory]
+    #[arg(short, long)]
+    dir: Option<Utf8PathBuf>,
+    /// error, warn, info, debug, trace or off [default: info]
+    #[arg(short, long)]
+    log_level: Option<LevelFilter>,
+    /// Config file [default: custard.toml in the served directory, if there is one]
+    #[arg(short, long)]
+    config: Option<Utf8PathBuf>,
+}
+
 async fn run() -> anyhow::Result<()> {
-    let mut args = std::env::args();
-    let socket_path = args
-        .nth(1)
-        .ok_or_else(|| anyhow!("Expected a socket path as a first argument"))?;
-    if let Some(wd) = args.next() {
-        std::env::set_current_dir(wd)?;
+    let cli = Cli::parse();
+
+    let current_dir: Utf8PathBuf = std::env::current_dir()?.try_into()?;
+    let config = Config::find(
+        cli.config.as_deref(),
+        cli.dir.as_deref().unwrap_or(&current_dir),
+    )?;
+
+    let log_level = config.log_level(cli.log_level)?;
+    tracing_subscriber::fmt().with_max_level(log_level).init();
+
+    let socket_path = cli.socket.or(config.sock.socket.clone()).ok_or_else(|| {
+        anyhow!("Expected a socket path, either with --socket or in the config file")
+    })?;
+    // The socket path is resolved before changing directory
+    let socket_path = current_dir.join(socket_path);
+    let dir = cli.dir.as_ref().or(config.dir.as_ref());
+    if let Some(dir) = dir {
+        std::env::set_current_dir(dir)?;
     }
 
-    let socket_path = std::path::Path::new(&socket_path);
     if socket_path.exists() {
-        std::fs::remove_file(socket_path)?;
+        std::fs::remove_file(&socket_path)?;
     }
 
     let current_dir: Utf8PathBuf = std::env::current_dir()?.try_into()?;
 
-    let keeper = custard_lib::frontmatter_file::Keeper::new(&current_dir)?;
-
-    let markdown_files = custard_lib::frontmatter_file::keeper::ArcMutex::new(keeper);
-
-    let mut watcher = notify::recommended_watcher(markdown_files.clone())?;
+    let mut collections = Collections::default();
+    // Keeps watching for as long as the socket is served
+    let mut watchers = Vec::new();
 
-    watcher.watch(current_dir.as_std_path(), RecursiveMode::NonRecursive)?;
+    // With only collections configured, the current directory isn't served as well
+    if dir.is_some() || config.collections.is_empty() {
+        let (markdown_files, watcher) = watch(&current_dir, config.keeper_options()?)?;
+        collections.default = Some(markdown_files);
+        watchers.push(watcher);
+    }
+    for (name, collection) in &config.collections {
+        let options = config.collection_options(collection)?;
+        let (markdown_files, watcher) = watch(&collection.dir, options)?;
+        collections.named.insert(name.clone(), markdown_files);
+        watchers.push(watcher);
+    }
 
-    let listener = UnixListener::bind(socket_path)?;
+    let listener = UnixListener::bind(&socket_path)?;
 
-    accept_streams(markdown_files, listener).await;
+    accept_streams(collections, listener).await;
 
     Ok(())
 }
 
 #[tokio::main]
 async fn main() {
-    tracing_subscriber::fmt::init();
-
     if let Err(err) = run().await {
         eprintln!("Error: {err}");
         std::process::exit(1);
@@ -199,4 +400,115 @@ mod test {
         let hex = format!("{bytes:x?}");
         assert_eq!("[92, a2, 4f, 6b, 1]", hex);
     }
+
+    #[test]
+    fn request_unit_value_decodes() {
+        #[derive(Serialize)]
+        struct Tagged {
+            tag: &'static str,
+            value: (),
+        }
+        let bytes = rmp_serde::to_vec_named(&Tagged {
+            tag: "BrokenLinks",
+            value: (),
+        })
+        .unwrap();
+        assert!(matches!(
+            rmp_serde::from_slice::<Envelope>(&bytes),
+            Ok(Envelope {
+                request: Request::BrokenLinks(()),
+                collection: None,
+            })
+        ));
+    }
+
+    #[test]
+    fn request_collection() {
+        #[derive(Serialize)]
+        struct Tagged {
+            tag: &'static str,
+            value: (),
+            collection: &'static str,
+        }
+        let bytes = rmp_serde::to_vec_named(&Tagged {
+            tag: "Errors",
+            value: (),
+            collection: "blog",
+        })
+        .unwrap();
+        assert!(matches!(
+            rmp_serde::from_slice::<Envelope>(&bytes),
+            Ok(Envelope {
+                request: Request::Errors(()),
+                collection: Some("blog"),
+            })
+        ));
+
+        let (out_buf, _) = in_buf_2_out_buf(&Collections::default(), &bytes);
+        let expected = rmp_serde::to_vec(&Result::<()>::BadRequest(
+            "Unknown collection: blog".to_owned(),
+        ))
+        .unwrap();
+        assert_eq!(expected, out_buf);
+    }
+
+    #[test]
+    fn request_borrows() {
+        #[derive(Serialize)]
+        struct Tagged {
+            tag: &'static str,
+            value: serde_json::Value,
+            collection: &'static str,
+        }
+        let bytes = rmp_serde::to_vec_named(&Tagged {
+            tag: "Single",
+            value: serde_json::json!({ "name": "a.md", "sort": [{ "key": "title" }] }),
+            collection: "blog",
+        })
+        .unwrap();
+        let Envelope {
+            request: Request::Single(args),
+            collection,
+        } = rmp_serde::from_slice::<Envelope>(&bytes).unwrap()
+        else {
+            panic!("expected a single request");
+        };
+        assert_eq!("a.md", args.name);
+        assert_eq!("title", args.sort[0].key);
+        assert_eq!(Some("blog"), collection);
+    }
+
+    #[test]
+    fn request_subscribe() {
+        #[derive(Serialize)]
+        struct Tagged {
+            tag: &'static str,
+            value: serde_json::Value,
+        }
+        let bytes = rmp_serde::to_vec_named(&Tagged {
+            tag: "Subscribe",
+            value: serde_json::json!({ "query": { "draft": false } }),
+        })
+        .unwrap();
+        let collections = Collections {
+            default: Some(ArcRwLock::new(Keeper::from_files(
+                Utf8PathBuf::from("/"),
+                HashMap::new(),
+            ))),
+            named: HashMap::new(),
+        };
+
+        let (out_buf, subscription) = in_buf_2_out_buf(&collections, &bytes);
+        assert_eq!(rmp_serde::to_vec(&Result::Ok(())).unwrap(), out_buf);
+        let subscription = subscription.expect("a subscription is kept open");
+        assert!(subscription.query.is_some());
+
+        let bytes = rmp_serde::to_vec_named(&Tagged {
+            tag: "Subscribe",
+            value: serde_json::json!({}),
+        })
+        .unwrap();
+        let (_, subscription) = in_buf_2_out_buf(&collections, &bytes);
+        assert!(subscription.is_some_and(|subscription| subscription.query.is_none()));
+    }
 }
@@ -1,8 +1,10 @@
 package sock
 
 import (
+	"bufio"
 	"encoding/binary"
 	"fmt"
+	"io"
 	"math"
 	"net"
 
@@ -10,8 +12,9 @@ import (
 )
 
 type taggedRequest struct {
-	Tag   string `msgpack:"tag"`
-	Value any    `msgpack:"value"`
+	Tag        string `msgpack:"tag"`
+	Value      any    `msgpack:"value"`
+	Collection string `msgpack:"collection,omitempty"`
 }
 
 type taggedResponse struct {
@@ -19,69 +22,192 @@ type taggedResponse struct {
 	Value msgpack.RawMessage `msgpack:"value"`
 }
 
-type Query struct {
-	Map       map[string]any `msgpack:"map"`
-	Intersect bool           `msgpack:"intersect"`
+// Query is a frontmatter query node: either a map of frontmatter keys to
+// values, or one of {"$and": []Query}, {"$or": []Query}, {"$not": Query} or
+// {"$intersect": map}.
+type Query map[string]any
+
+// SortSpec is one key to sort by. If Desc is nil, the request's OrderDesc is
+// used.
+type SortSpec struct {
+	Key  string `msgpack:"key"`
+	Desc *bool  `msgpack:"desc,omitempty"`
 }
 
 type SingleRequest struct {
-	Name      string `msgpack:"name"`
-	Query     *Query `msgpack:"query,omitempty"`
-	SortKey   string `msgpack:"sort_key,omitempty"`
-	OrderDesc bool   `msgpack:"order_desc,omitempty"`
+	Name       string     `msgpack:"name"`
+	Query      Query      `msgpack:"query,omitempty"`
+	Sort       []SortSpec `msgpack:"sort,omitempty"`
+	OrderDesc  bool       `msgpack:"order_desc,omitempty"`
+	NullsFirst bool       `msgpack:"nulls_first,omitempty"`
+	RenderHTML bool       `msgpack:"render_html,omitempty"`
+	// Links includes the file's links and backlinks in the response.
+	Links      bool       `msgpack:"links,omitempty"`
 }
 
 type ListRequest struct {
-	Query     *Query `msgpack:"query,omitempty"`
-	SortKey   string `msgpack:"sort_key,omitempty"`
-	OrderDesc bool   `msgpack:"order_desc,omitempty"`
-	Offset    uint   `msgpack:"offset,omitempty"`
-	Limit     uint   `msgpack:"limit,omitempty"`
+	Query      Query      `msgpack:"query,omitempty"`
+	Sort       []SortSpec `msgpack:"sort,omitempty"`
+	OrderDesc  bool       `msgpack:"order_desc,omitempty"`
+	NullsFirst bool       `msgpack:"nulls_first,omitempty"`
+	Offset     uint       `msgpack:"offset,omitempty"`
+	Limit      uint       `msgpack:"limit,omitempty"`
+	After      string     `msgpack:"after,omitempty"`
+	Before     string     `msgpack:"before,omitempty"`
+	// Fields, if set, limits each file to these frontmatter keys and/or
+	// "name", "created", "modified", "one_liner", "excerpt" and "outline".
+	// The response is then encoded with field names rather than positionally,
+	// leaving out the fields that weren't asked for.
+	Fields     []string   `msgpack:"fields,omitempty"`
+	// Excerpt is one of {"chars": n}, {"words": n}, "paragraph" or "more".
+	Excerpt    any        `msgpack:"excerpt,omitempty"`
+	Outline    bool       `msgpack:"outline,omitempty"`
 }
 
 type CollateRequest struct {
-	Query *Query `msgpack:"query,omitempty"`
+	Query Query  `msgpack:"query,omitempty"`
 	Key   string `msgpack:"key"`
 }
 
+type SearchRequest struct {
+	Text   string `msgpack:"text"`
+	Query  Query  `msgpack:"query,omitempty"`
+	Offset uint   `msgpack:"offset,omitempty"`
+	Limit  uint   `msgpack:"limit,omitempty"`
+}
+
+type Heading struct {
+	Level uint8  `msgpack:"level"`
+	Text  string `msgpack:"text"`
+	Slug  string `msgpack:"slug"`
+}
+
+type LinksRequest struct {
+	Name string `msgpack:"name"`
+}
+
+type Link struct {
+	Kind   string `msgpack:"kind"`
+	Target string `msgpack:"target"`
+	// Name is the file linked to, or empty if the link is broken.
+	Name string `msgpack:"name"`
+}
+
+type BrokenLink struct {
+	From   string `msgpack:"from"`
+	Kind   string `msgpack:"kind"`
+	Target string `msgpack:"target"`
+}
+
+type LinksResponse struct {
+	Links     []Link   `msgpack:"links"`
+	Backlinks []string `msgpack:"backlinks"`
+}
+
+// LoadError is a file that couldn't be loaded, or was loaded without its
+// frontmatter.
+type LoadError struct {
+	Name    string `msgpack:"name"`
+	Message string `msgpack:"message"`
+}
+
 type FileResponse struct {
 	Name        string         `msgpack:"name"`
 	Frontmatter map[string]any `msgpack:"frontmatter,omitempty"`
 	Body        string         `msgpack:"body"`
 	Modified    string         `msgpack:"modified"`
 	Created     string         `msgpack:"created"`
+	Outline     []Heading      `msgpack:"outline"`
 }
 
 type SingleResponse struct {
 	File         FileResponse `msgpack:"file"`
 	PrevFileName string       `msgpack:"prev_file_name"`
 	NextFileName string       `msgpack:"next_file_name"`
+	HTML         string       `msgpack:"html"`
+	Links        []Link       `msgpack:"links"`
+	Backlinks    []string     `msgpack:"backlinks"`
 }
 
 type ShortResponse struct {
 	Name        string         `msgpack:"name"`
 	Frontmatter map[string]any `msgpack:"frontmatter,omitempty"`
 	OneLiner    string         `msgpack:"one_liner,omitempty"`
+	Excerpt     string         `msgpack:"excerpt,omitempty"`
+	Outline     []Heading      `msgpack:"outline,omitempty"`
 	Modified    string         `msgpack:"modified"`
 	Created     string         `msgpack:"created"`
 }
 
 type ListResponse struct {
-	Files []ShortResponse `msgpack:"files"`
-	Total uint            `msgpack:"total"`
+	Files      []ShortResponse `msgpack:"files"`
+	Total      uint            `msgpack:"total"`
+	NextCursor string          `msgpack:"next_cursor"`
+	PrevCursor string          `msgpack:"prev_cursor"`
+}
+
+type SearchHit struct {
+	File    ShortResponse `msgpack:"file"`
+	Score   float64       `msgpack:"score"`
+	Snippet string        `msgpack:"snippet"`
+}
+
+type SearchResponse struct {
+	Hits  []SearchHit `msgpack:"hits"`
+	Total uint        `msgpack:"total"`
+}
+
+type SubscribeRequest struct {
+	// Query, if set, limits the changes to files matching it before or after
+	// the change. A file that starts or stops matching is reported as
+	// "Created" or "Deleted".
+	Query Query `msgpack:"query,omitempty"`
+}
+
+// ChangeEvent is a change to the files served. Tag is one of "Created",
+// "Edited", "Renamed", "Deleted" or "Lagged".
+type ChangeEvent struct {
+	Tag string
+	// File is the file after the change, for "Created", "Edited" and
+	// "Renamed".
+	File *ShortResponse
+	// Name is the file's name before the change, for "Renamed" and "Deleted".
+	Name string
+	// Missed is how many changes were missed by falling behind, for "Lagged".
+	Missed uint64
+}
+
+type renamedEvent struct {
+	From string        `msgpack:"from"`
+	File ShortResponse `msgpack:"file"`
+}
+
+// Subscription is a stream of changes, which stays open until it is closed.
+type Subscription struct {
+	conn   net.Conn
+	reader *bufio.Reader
 }
 
 type Client struct {
 	socketPath string
+	collection string
 }
 
 func NewClient(socketPath string) *Client {
 	c := Client{
-		socketPath,
+		socketPath: socketPath,
 	}
 	return &c
 }
 
+// WithCollection returns a client whose requests go to the named collection,
+// rather than the default one.
+func (c *Client) WithCollection(name string) *Client {
+	collected := *c
+	collected.collection = name
+	return &collected
+}
+
 func encodeUint32BufLength(buf []byte) ([]byte, error) {
 	bufLength := len(buf)
 	if bufLength > math.MaxUint32 {
@@ -100,8 +226,9 @@ func (c *Client) List(req ListRequest) (*ListResponse, error) {
 	defer conn.Close()
 
 	listReq := taggedRequest{
-		Tag:   "List",
-		Value: req,
+		Tag:        "List",
+		Value:      req,
+		Collection: c.collection,
 	}
 
 	enc := msgpack.NewEncoder(conn)
@@ -137,6 +264,13 @@ func (c *Client) List(req ListRequest) (*ListResponse, error) {
 			return nil, fmt.Errorf("Could not unmarshal response value: %w", err)
 		}
 		return &listResp, nil
+	case "BadRequest":
+		var message string
+		err := msgpack.Unmarshal(resp.Value, &message)
+		if err != nil {
+			return nil, fmt.Errorf("Could not unmarshal response value: %w", err)
+		}
+		return nil, fmt.Errorf("Custard rejected request: %s", message)
 	case "InternalServerError":
 		return nil, fmt.Errorf("Custard had internal server error")
 	default:
@@ -152,8 +286,9 @@ func (c *Client) Single(req SingleRequest) (*SingleResponse, error) {
 	defer conn.Close()
 
 	singleReq := taggedRequest{
-		Tag:   "Single",
-		Value: req,
+		Tag:        "Single",
+		Value:      req,
+		Collection: c.collection,
 	}
 
 	enc := msgpack.NewEncoder(conn)
@@ -192,6 +327,13 @@ func (c *Client) Single(req SingleRequest) (*SingleResponse, error) {
 			return nil, fmt.Errorf("Could not unmarshal response value: %w", err)
 		}
 		return &singleResp, nil
+	case "BadRequest":
+		var message string
+		err := msgpack.Unmarshal(resp.Value, &message)
+		if err != nil {
+			return nil, fmt.Errorf("Could not unmarshal response value: %w", err)
+		}
+		return nil, fmt.Errorf("Custard rejected request: %s", message)
 	case "InternalServerError":
 		return nil, fmt.Errorf("Custard had internal server error")
 	default:
@@ -207,8 +349,9 @@ func (c *Client) Collate(req CollateRequest) ([]string, error) {
 	defer conn.Close()
 
 	collateReq := taggedRequest{
-		Tag:   "Collate",
-		Value: req,
+		Tag:        "Collate",
+		Value:      req,
+		Collection: c.collection,
 	}
 
 	enc := msgpack.NewEncoder(conn)
@@ -244,9 +387,368 @@ func (c *Client) Collate(req CollateRequest) ([]string, error) {
 			return nil, fmt.Errorf("Could not unmarshal response value: %w", err)
 		}
 		return collateResp, nil
+	case "BadRequest":
+		var message string
+		err := msgpack.Unmarshal(resp.Value, &message)
+		if err != nil {
+			return nil, fmt.Errorf("Could not unmarshal response value: %w", err)
+		}
+		return nil, fmt.Errorf("Custard rejected request: %s", message)
 	case "InternalServerError":
 		return nil, fmt.Errorf("Custard had internal server error")
 	default:
 		return nil, fmt.Errorf("Unrecognised tag from server: %s", resp.Tag)
 	}
 }
+
+func (c *Client) Search(req SearchRequest) (*SearchResponse, error) {
+	conn, err := net.Dial("unix", c.socketPath)
+	if err != nil {
+		return nil, fmt.Errorf("Failed to dial: %w", err)
+	}
+	defer conn.Close()
+
+	searchReq := taggedRequest{
+		Tag:        "Search",
+		Value:      req,
+		Collection: c.collection,
+	}
+
+	enc := msgpack.NewEncoder(conn)
+	enc.UseArrayEncodedStructs(false)
+	buf, err := msgpack.Marshal(searchReq)
+	if err != nil {
+		return nil, fmt.Errorf("Failed to encode request: %w", err)
+	}
+	lengthBytes, err := encodeUint32BufLength(buf)
+	if err != nil {
+		return nil, fmt.Errorf("Failed to encode request length: %w", err)
+	}
+	_, err = conn.Write(lengthBytes)
+	if err != nil {
+		return nil, fmt.Errorf("Failed to send request length: %w", err)
+	}
+	_, err = conn.Write(buf)
+	if err != nil {
+		return nil, fmt.Errorf("Failed to send request: %w", err)
+	}
+
+	var resp *taggedResponse
+	dec := msgpack.NewDecoder(conn)
+	if err := dec.Decode(&resp); err != nil {
+		return nil, fmt.Errorf("Failed to decode response: %w", err)
+	}
+
+	switch resp.Tag {
+	case "Ok":
+		var searchResp SearchResponse
+		err := msgpack.Unmarshal(resp.Value, &searchResp)
+		if err != nil {
+			return nil, fmt.Errorf("Could not unmarshal response value: %w", err)
+		}
+		return &searchResp, nil
+	case "BadRequest":
+		var message string
+		err := msgpack.Unmarshal(resp.Value, &message)
+		if err != nil {
+			return nil, fmt.Errorf("Could not unmarshal response value: %w", err)
+		}
+		return nil, fmt.Errorf("Custard rejected request: %s", message)
+	case "InternalServerError":
+		return nil, fmt.Errorf("Custard had internal server error")
+	default:
+		return nil, fmt.Errorf("Unrecognised tag from server: %s", resp.Tag)
+	}
+}
+
+func (c *Client) Links(req LinksRequest) (*LinksResponse, error) {
+	conn, err := net.Dial("unix", c.socketPath)
+	if err != nil {
+		return nil, fmt.Errorf("Failed to dial: %w", err)
+	}
+	defer conn.Close()
+
+	linksReq := taggedRequest{
+		Tag:        "Links",
+		Value:      req,
+		Collection: c.collection,
+	}
+
+	enc := msgpack.NewEncoder(conn)
+	enc.UseArrayEncodedStructs(false)
+	buf, err := msgpack.Marshal(linksReq)
+	if err != nil {
+		return nil, fmt.Errorf("Failed to encode request: %w", err)
+	}
+	lengthBytes, err := encodeUint32BufLength(buf)
+	if err != nil {
+		return nil, fmt.Errorf("Failed to encode request length: %w", err)
+	}
+	_, err = conn.Write(lengthBytes)
+	if err != nil {
+		return nil, fmt.Errorf("Failed to send request length: %w", err)
+	}
+	_, err = conn.Write(buf)
+	if err != nil {
+		return nil, fmt.Errorf("Failed to send request: %w", err)
+	}
+
+	var resp *taggedResponse
+	dec := msgpack.NewDecoder(conn)
+	if err := dec.Decode(&resp); err != nil {
+		return nil, fmt.Errorf("Failed to decode response: %w", err)
+	}
+
+	switch resp.Tag {
+	case "Ok":
+		if resp.Value == nil {
+			return nil, nil
+		}
+		var linksResp LinksResponse
+		err := msgpack.Unmarshal(resp.Value, &linksResp)
+		if err != nil {
+			return nil, fmt.Errorf("Could not unmarshal response value: %w", err)
+		}
+		return &linksResp, nil
+	case "BadRequest":
+		var message string
+		err := msgpack.Unmarshal(resp.Value, &message)
+		if err != nil {
+			return nil, fmt.Errorf("Could not unmarshal response value: %w", err)
+		}
+		return nil, fmt.Errorf("Custard rejected request: %s", message)
+	case "InternalServerError":
+		return nil, fmt.Errorf("Custard had internal server error")
+	default:
+		return nil, fmt.Errorf("Unrecognised tag from server: %s", resp.Tag)
+	}
+}
+
+func (c *Client) BrokenLinks() ([]BrokenLink, error) {
+	conn, err := net.Dial("unix", c.socketPath)
+	if err != nil {
+		return nil, fmt.Errorf("Failed to dial: %w", err)
+	}
+	defer conn.Close()
+
+	brokenLinksReq := taggedRequest{
+		Tag:        "BrokenLinks",
+		Value:      nil,
+		Collection: c.collection,
+	}
+
+	enc := msgpack.NewEncoder(conn)
+	enc.UseArrayEncodedStructs(false)
+	buf, err := msgpack.Marshal(brokenLinksReq)
+	if err != nil {
+		return nil, fmt.Errorf("Failed to encode request: %w", err)
+	}
+	lengthBytes, err := encodeUint32BufLength(buf)
+	if err != nil {
+		return nil, fmt.Errorf("Failed to encode request length: %w", err)
+	}
+	_, err = conn.Write(lengthBytes)
+	if err != nil {
+		return nil, fmt.Errorf("Failed to send request length: %w", err)
+	}
+	_, err = conn.Write(buf)
+	if err != nil {
+		return nil, fmt.Errorf("Failed to send request: %w", err)
+	}
+
+	var resp *taggedResponse
+	dec := msgpack.NewDecoder(conn)
+	if err := dec.Decode(&resp); err != nil {
+		return nil, fmt.Errorf("Failed to decode response: %w", err)
+	}
+
+	switch resp.Tag {
+	case "Ok":
+		var brokenLinksResp []BrokenLink
+		err := msgpack.Unmarshal(resp.Value, &brokenLinksResp)
+		if err != nil {
+			return nil, fmt.Errorf("Could not unmarshal response value: %w", err)
+		}
+		return brokenLinksResp, nil
+	case "BadRequest":
+		var message string
+		err := msgpack.Unmarshal(resp.Value, &message)
+		if err != nil {
+			return nil, fmt.Errorf("Could not unmarshal response value: %w", err)
+		}
+		return nil, fmt.Errorf("Custard rejected request: %s", message)
+	case "InternalServerError":
+		return nil, fmt.Errorf("Custard had internal server error")
+	default:
+		return nil, fmt.Errorf("Unrecognised tag from server: %s", resp.Tag)
+	}
+}
+
+func (c *Client) Errors() ([]LoadError, error) {
+	conn, err := net.Dial("unix", c.socketPath)
+	if err != nil {
+		return nil, fmt.Errorf("Failed to dial: %w", err)
+	}
+	defer conn.Close()
+
+	errorsReq := taggedRequest{
+		Tag:        "Errors",
+		Value:      nil,
+		Collection: c.collection,
+	}
+
+	enc := msgpack.NewEncoder(conn)
+	enc.UseArrayEncodedStructs(false)
+	buf, err := msgpack.Marshal(errorsReq)
+	if err != nil {
+		return nil, fmt.Errorf("Failed to encode request: %w", err)
+	}
+	lengthBytes, err := encodeUint32BufLength(buf)
+	if err != nil {
+		return nil, fmt.Errorf("Failed to encode request length: %w", err)
+	}
+	_, err = conn.Write(lengthBytes)
+	if err != nil {
+		return nil, fmt.Errorf("Failed to send request length: %w", err)
+	}
+	_, err = conn.Write(buf)
+	if err != nil {
+		return nil, fmt.Errorf("Failed to send request: %w", err)
+	}
+
+	var resp *taggedResponse
+	dec := msgpack.NewDecoder(conn)
+	if err := dec.Decode(&resp); err != nil {
+		return nil, fmt.Errorf("Failed to decode response: %w", err)
+	}
+
+	switch resp.Tag {
+	case "Ok":
+		var errorsResp []LoadError
+		err := msgpack.Unmarshal(resp.Value, &errorsResp)
+		if err != nil {
+			return nil, fmt.Errorf("Could not unmarshal response value: %w", err)
+		}
+		return errorsResp, nil
+	case "BadRequest":
+		var message string
+		err := msgpack.Unmarshal(resp.Value, &message)
+		if err != nil {
+			return nil, fmt.Errorf("Could not unmarshal response value: %w", err)
+		}
+		return nil, fmt.Errorf("Custard rejected request: %s", message)
+	case "InternalServerError":
+		return nil, fmt.Errorf("Custard had internal server error")
+	default:
+		return nil, fmt.Errorf("Unrecognised tag from server: %s", resp.Tag)
+	}
+}
+
+// Subscribe opens a stream of changes to the files, as they are made.
+func (c *Client) Subscribe(req SubscribeRequest) (*Subscription, error) {
+	conn, err := net.Dial("unix", c.socketPath)
+	if err != nil {
+		return nil, fmt.Errorf("Failed to dial: %w", err)
+	}
+
+	subscribeReq := taggedRequest{
+		Tag:        "Subscribe",
+		Value:      req,
+		Collection: c.collection,
+	}
+
+	buf, err := msgpack.Marshal(subscribeReq)
+	if err != nil {
+		conn.Close()
+		return nil, fmt.Errorf("Failed to encode request: %w", err)
+	}
+	lengthBytes, err := encodeUint32BufLength(buf)
+	if err != nil {
+		conn.Close()
+		return nil, fmt.Errorf("Failed to encode request length: %w", err)
+	}
+	_, err = conn.Write(lengthBytes)
+	if err != nil {
+		conn.Close()
+		return nil, fmt.Errorf("Failed to send request length: %w", err)
+	}
+	_, err = conn.Write(buf)
+	if err != nil {
+		conn.Close()
+		return nil, fmt.Errorf("Failed to send request: %w", err)
+	}
+
+	// The decoder mustn't read past the response into the events after it
+	reader := bufio.NewReader(conn)
+	var resp *taggedResponse
+	dec := msgpack.NewDecoder(reader)
+	if err := dec.Decode(&resp); err != nil {
+		conn.Close()
+		return nil, fmt.Errorf("Failed to decode response: %w", err)
+	}
+
+	switch resp.Tag {
+	case "Ok":
+		return &Subscription{conn: conn, reader: reader}, nil
+	case "BadRequest":
+		conn.Close()
+		var message string
+		err := msgpack.Unmarshal(resp.Value, &message)
+		if err != nil {
+			return nil, fmt.Errorf("Could not unmarshal response value: %w", err)
+		}
+		return nil, fmt.Errorf("Custard rejected request: %s", message)
+	case "InternalServerError":
+		conn.Close()
+		return nil, fmt.Errorf("Custard had internal server error")
+	default:
+		conn.Close()
+		return nil, fmt.Errorf("Unrecognised tag from server: %s", resp.Tag)
+	}
+}
+
+// Next waits for the next change.
+func (s *Subscription) Next() (*ChangeEvent, error) {
+	lengthBytes := make([]byte, 4)
+	_, err := io.ReadFull(s.reader, lengthBytes)
+	if err != nil {
+		return nil, fmt.Errorf("Failed to read event length: %w", err)
+	}
+	buf := make([]byte, binary.BigEndian.Uint32(lengthBytes))
+	_, err = io.ReadFull(s.reader, buf)
+	if err != nil {
+		return nil, fmt.Errorf("Failed to read event: %w", err)
+	}
+
+	var tagged taggedResponse
+	if err := msgpack.Unmarshal(buf, &tagged); err != nil {
+		return nil, fmt.Errorf("Failed to decode event: %w", err)
+	}
+
+	event := ChangeEvent{Tag: tagged.Tag}
+	switch tagged.Tag {
+	case "Created", "Edited":
+		var file ShortResponse
+		err = msgpack.Unmarshal(tagged.Value, &file)
+		event.File = &file
+	case "Renamed":
+		var renamed renamedEvent
+		err = msgpack.Unmarshal(tagged.Value, &renamed)
+		event.Name = renamed.From
+		event.File = &renamed.File
+	case "Deleted":
+		err = msgpack.Unmarshal(tagged.Value, &event.Name)
+	case "Lagged":
+		err = msgpack.Unmarshal(tagged.Value, &event.Missed)
+	default:
+		return nil, fmt.Errorf("Unrecognised event from server: %s", tagged.Tag)
+	}
+	if err != nil {
+		return nil, fmt.Errorf("Could not unmarshal event value: %w", err)
+	}
+	return &event, nil
+}
+
+func (s *Subscription) Close() error {
+	return s.conn.Close()
+}
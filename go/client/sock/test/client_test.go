@@ -50,10 +50,8 @@ func TestQuerySingle(t *testing.T) {
 	client := sock.NewClient("/tmp/custard")
 	resp, err := client.Single(sock.SingleRequest{
 		Name: "chai-cheese.md",
-		Query: &sock.Query{
-			Map: map[string]any{
-				"tags": []string{"code"},
-			},
+		Query: sock.Query{
+			"tags": []string{"code"},
 		},
 	})
 	if err != nil {
@@ -94,10 +92,8 @@ func TestGetList(t *testing.T) {
 func TestQueryList(t *testing.T) {
 	client := sock.NewClient("/tmp/custard")
 	resp, err := client.List(sock.ListRequest{
-		Query: &sock.Query{
-			Map: map[string]any{
-				"tags": []string{"code"},
-			},
+		Query: sock.Query{
+			"tags": []string{"code"},
 		},
 	})
 	if err != nil {
@@ -130,10 +126,8 @@ func TestQueryCollate(t *testing.T) {
 	client := sock.NewClient("/tmp/custard")
 	resp, err := client.Collate(sock.CollateRequest{
 		Key: "tags",
-		Query: &sock.Query{
-			Map: map[string]any{
-				"tags": []string{"code"},
-			},
+		Query: sock.Query{
+			"tags": []string{"code"},
 		},
 	})
 	if err != nil {